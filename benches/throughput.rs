@@ -0,0 +1,190 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use chip_8_emulator::{
+    cpu::{CPU, SCREEN_HEIGHT, SCREEN_WIDTH},
+    dirty::DirtyRegion,
+    gpu::{CaptureRenderer, FrameView, NullRenderer, Renderer, TerminalRenderer},
+    opcodes::opcode_d_xyn,
+    phosphor::Phosphor,
+    quirks::Quirks,
+};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+// Everything runs from a fixed seed and a fixed snapshot, so the numbers of
+// two commits measure the same work. Compare a change against master with:
+//
+//   git checkout master && cargo bench -- --save-baseline master
+//   git checkout -      && cargo bench -- --baseline master
+//
+// The SDL2 renderer is only measured with --features sdl. It draws offscreen,
+// so no window opens.
+
+// SCTEST is left out, it stops on a SUPER-CHIP instruction before the
+// warm-up is over
+const ROMS: [&str; 3] = ["INVADERS.ch8", "TETRIS.ch8", "TEST.ch8"];
+const SEED: u64 = 0;
+// Frames run before measuring, so the games are past their title screens
+const WARM_UP_FRAMES: u64 = 120;
+const INSTRUCTIONS: u64 = 1000;
+// The frontend defaults of main.rs
+const PHOSPHOR_FADE_FRAMES: u8 = 3;
+const CAPTURE_FILTERS: &str = "scale=4";
+#[cfg(feature = "sdl")]
+const SDL_FILTERS: &str = "scale=10,scanlines=0.35,bloom=0.4,curvature=0.05";
+
+fn load(rom: &str) -> CPU {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("ROMS").join(rom);
+
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    cpu.load_rom(&path).unwrap();
+    cpu.reseed(SEED);
+
+    for _ in 0..WARM_UP_FRAMES {
+        cpu.run_frame().unwrap();
+    }
+
+    cpu
+}
+
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+
+    for rom in ROMS {
+        let mut cpu = load(rom);
+        let snapshot = cpu.save_state();
+
+        // Every run starts from the snapshot, so it runs the same code
+        group.bench_function(rom, |b| {
+            b.iter_custom(|iterations| {
+                let mut elapsed = Duration::ZERO;
+
+                for _ in 0..iterations {
+                    cpu.load_state(&snapshot).unwrap();
+
+                    let start = Instant::now();
+                    for _ in 0..INSTRUCTIONS {
+                        cpu.update().unwrap();
+                    }
+                    elapsed += start.elapsed();
+                }
+
+                elapsed
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn draw(c: &mut Criterion) {
+    let mut group = c.benchmark_group("opcode_d_xyn");
+
+    // Name, quirks, X and Y of a 15 row sprite
+    let cases = [
+        ("inside", Quirks::default(), 20, 8),
+        ("clipped", Quirks::default(), 60, 28),
+        ("wrapped", Quirks { wrap: true, ..Quirks::default() }, 60, 28),
+    ];
+
+    for (name, quirks, x, y) in cases {
+        let mut cpu = CPU::new();
+        cpu.initialize();
+        cpu.quirks = quirks;
+        cpu.registers[0] = x;
+        cpu.registers[1] = y;
+        // The font, every row has some pixels lit
+        cpu.index_register = 0;
+
+        // Drawing twice erases the sprite again, so the screen only ever
+        // holds one copy
+        group.bench_function(name, |b| b.iter(|| opcode_d_xyn(&mut cpu, 0xD01F).unwrap()));
+    }
+
+    group.finish();
+}
+
+fn save_state(c: &mut Criterion) {
+    let mut group = c.benchmark_group("save_state");
+    let mut cpu = load("INVADERS.ch8");
+    let state = cpu.save_state();
+
+    group.bench_function("save", |b| b.iter(|| cpu.save_state()));
+    group.bench_function("load", |b| b.iter(|| cpu.load_state(&state).unwrap()));
+
+    group.finish();
+}
+
+fn present(c: &mut Criterion) {
+    let mut group = c.benchmark_group("present");
+
+    // A game frame after the phosphor filter, with everything marked as
+    // changed like after a clear screen
+    let cpu = load("INVADERS.ch8");
+    let mut phosphor = Phosphor::new(PHOSPHOR_FADE_FRAMES);
+    let mut dirty = DirtyRegion::default();
+    dirty.mark_all(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let brightness = phosphor.update(&cpu.framebuffer, SCREEN_WIDTH, &mut dirty).to_vec();
+
+    let frame = FrameView {
+        width: SCREEN_WIDTH,
+        height: SCREEN_HEIGHT,
+        brightness: &brightness,
+        dirty: &dirty,
+    };
+
+    let mut null = NullRenderer::default();
+    group.bench_function("null", |b| b.iter(|| null.present(&frame).unwrap()));
+
+    let mut terminal = TerminalRenderer::headless(io::sink());
+    group.bench_function("terminal", |b| b.iter(|| terminal.present(&frame).unwrap()));
+
+    // Every frame becomes a file, so each sample gets a directory that is
+    // deleted again afterwards
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("present");
+    group.bench_function("capture", |b| {
+        b.iter_custom(|iterations| {
+            let filters = CAPTURE_FILTERS.parse().unwrap();
+            let mut capture = CaptureRenderer::new(directory.clone(), filters).unwrap();
+
+            let start = Instant::now();
+            for _ in 0..iterations {
+                capture.present(&frame).unwrap();
+            }
+            let elapsed = start.elapsed();
+
+            fs::remove_dir_all(&directory).unwrap();
+            elapsed
+        })
+    });
+
+    #[cfg(feature = "sdl")]
+    {
+        std::env::set_var("SDL_VIDEODRIVER", "offscreen");
+
+        let mut sdl = chip_8_emulator::gpu::SdlRenderer::new(
+            "bench",
+            SCREEN_WIDTH,
+            SCREEN_HEIGHT,
+            SDL_FILTERS.parse().unwrap(),
+            "integer".parse().unwrap(),
+            false,
+        )
+        .unwrap();
+        group.bench_function("sdl", |b| b.iter(|| sdl.present(&frame).unwrap()));
+    }
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().measurement_time(Duration::from_secs(3));
+    targets = update, draw, save_state, present
+}
+criterion_main!(benches);
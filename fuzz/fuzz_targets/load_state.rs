@@ -0,0 +1,32 @@
+#![no_main]
+
+// Loads the input as a save state and runs from there. A state that does not
+// load must be rejected with an error, one that does must be safe to run.
+//
+//   cargo +nightly fuzz run load_state
+//
+// The corpus starts out as states of the ROMs in ROMS/ after a few frames.
+
+use chip_8_emulator::cpu::CPU;
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u64 = 30;
+
+fuzz_target!(|state: &[u8]| {
+    let mut cpu = CPU::new();
+    cpu.initialize();
+
+    if cpu.load_state(state).is_err() {
+        return;
+    }
+
+    for _ in 0..FRAMES {
+        if cpu.run_frame().is_err() {
+            break;
+        }
+    }
+
+    // Whatever loaded saves and loads again
+    let saved = cpu.save_state();
+    cpu.load_state(&saved).unwrap();
+});
@@ -0,0 +1,41 @@
+#![no_main]
+
+// Loads the input as a ROM and runs it for a bounded number of frames under
+// every quirks profile. Bad programs must end in an error, never a panic.
+//
+//   cargo +nightly fuzz run run_rom
+//
+// The corpus starts out as the ROMs in ROMS/.
+
+use chip_8_emulator::{
+    cpu::CPU,
+    quirks::{Quirks, PROFILES},
+};
+use libfuzzer_sys::fuzz_target;
+
+const FRAMES: u64 = 30;
+
+fuzz_target!(|rom: &[u8]| {
+    for &profile in PROFILES.iter() {
+        let mut cpu = CPU::new();
+        cpu.initialize();
+        cpu.reseed(0);
+
+        if cpu.load_program(rom).is_err() {
+            return;
+        }
+        cpu.quirks = Quirks::profile(profile).unwrap();
+
+        for frame in 0..FRAMES {
+            // Some input too, so the key instructions get to run
+            let key = (frame % 16) as u8;
+            cpu.key_down(key);
+
+            if cpu.run_frame().is_err() {
+                break;
+            }
+
+            cpu.key_up(key);
+        }
+    }
+});
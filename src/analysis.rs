@@ -0,0 +1,451 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt,
+};
+
+use crate::{cpu::MEMORY_SIZE, instruction::Instruction};
+
+// Static analysis of a ROM for the `info` subcommand. Code is found by
+// following the control flow from 0x200 with the same decoder the CPU uses,
+// so sprites and other data between the instructions are left out. Jumps
+// through BNNN can not be followed, code only reachable that way is missed.
+
+const START: u16 = 0x200;
+
+// The COSMAC VIP kept its stack and variables from 0xEA0 and the display from
+// 0xF00, programs for it stay below.
+pub const LAST_PROGRAM_ADDRESS: u16 = 0xE8F;
+
+// Levels of the CPU's call stack
+pub const STACK_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Family {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Family::Chip8 => write!(f, "CHIP-8"),
+            Family::SuperChip => write!(f, "SUPER-CHIP"),
+            Family::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+// An instruction touching memory past LAST_PROGRAM_ADDRESS
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighAccess {
+    pub at: u16,
+    pub pattern: &'static str,
+    pub access: Access,
+    // First and last address touched
+    pub start: u16,
+    pub end: u16,
+}
+
+#[derive(Debug, Default)]
+pub struct Analysis {
+    // Addresses of every reachable instruction
+    pub code: BTreeSet<u16>,
+    // Opcode patterns of the extensions, like "00FF", by family
+    pub extensions: BTreeMap<Family, BTreeSet<&'static str>>,
+    // Reachable opcodes that no interpreter knows, by address
+    pub unknown: BTreeMap<u16, u16>,
+    pub high_accesses: Vec<HighAccess>,
+    // Instructions accessing memory through an I that could not be worked out
+    pub unchecked_accesses: BTreeSet<u16>,
+    // BNNN jumps, their targets are not followed
+    pub computed_jumps: usize,
+    // Deepest nesting of subroutine calls, None when a subroutine can end up
+    // calling itself, either through recursion or by jumping back out into
+    // its caller instead of returning
+    pub call_depth: Option<usize>,
+    // Quirk-sensitive opcode patterns with their count and the quirks
+    pub quirky: BTreeMap<&'static str, (usize, &'static str)>,
+}
+
+impl Analysis {
+    pub fn families(&self) -> impl Iterator<Item = Family> + '_ {
+        std::iter::once(Family::Chip8).chain(self.extensions.keys().copied())
+    }
+
+    // Quirks profile that fits the instructions the ROM uses
+    pub fn suggested_profile(&self) -> &'static str {
+        if self.extensions.contains_key(&Family::XoChip) {
+            "xochip"
+        } else if self.extensions.contains_key(&Family::SuperChip) {
+            "schip"
+        } else {
+            crate::quirks::DEFAULT_PROFILE
+        }
+    }
+
+    pub fn call_stack_overflows(&self) -> bool {
+        self.call_depth.is_some_and(|depth| depth > STACK_DEPTH)
+    }
+}
+
+// How control continues after an instruction
+enum Flow {
+    Next,
+    Skip,
+    Jump(u16),
+    Call(u16),
+    Return,
+    Stop,
+}
+
+// Fails like CPU::load_program for a ROM that does not fit into memory.
+pub fn analyze(rom: &[u8]) -> Result<Analysis, String> {
+    if rom.len() > MEMORY_SIZE - START as usize {
+        return Err(format!(
+            "is {} bytes, a CHIP-8 program can be at most {}",
+            rom.len(),
+            MEMORY_SIZE - START as usize
+        ));
+    }
+
+    let program = Program { rom };
+    let mut analysis = Analysis::default();
+
+    // Walks the code together with the value of I where it is known, so the
+    // memory accesses can be checked
+    let mut seen = HashSet::new();
+    let mut pending: Vec<(u16, Option<u16>)> = vec![(START, Some(0))];
+
+    while let Some((at, index)) = pending.pop() {
+        if !seen.insert((at, index)) {
+            continue;
+        }
+
+        let Some(opcode) = program.opcode(at) else {
+            continue;
+        };
+
+        analysis.code.insert(at);
+
+        if at > LAST_PROGRAM_ADDRESS {
+            analysis.high_accesses.push(HighAccess {
+                at,
+                pattern: "code",
+                access: Access::Execute,
+                start: at,
+                end: at.saturating_add(program.size(at) - 1),
+            });
+        }
+
+        let instruction = Instruction::decode(opcode);
+
+        if let Some((pattern, access, length)) = memory_access(instruction, opcode) {
+            match index {
+                Some(start) => {
+                    let end = start.saturating_add(length - 1);
+
+                    if end > LAST_PROGRAM_ADDRESS {
+                        analysis.high_accesses.push(HighAccess {
+                            at,
+                            pattern,
+                            access,
+                            start,
+                            end,
+                        });
+                    }
+                }
+                None => {
+                    analysis.unchecked_accesses.insert(at);
+                }
+            }
+        }
+
+        let index = match instruction {
+            Instruction::LoadIndex(nnn) => Some(nnn),
+            Instruction::Unknown(0xF000) => at.checked_add(2).and_then(|at| program.opcode(at)),
+            // The font is at the start of memory, well out of the way
+            Instruction::LoadFont(_) => Some(0),
+            Instruction::AddIndex(_) => None,
+            _ => index,
+        };
+
+        match flow(instruction, at) {
+            Flow::Next => pending.extend(program.next(at).map(|next| (next, index))),
+            Flow::Skip => {
+                let next = program.next(at);
+                let skipped = next.and_then(|next| program.next(next));
+                pending.extend([next, skipped].into_iter().flatten().map(|at| (at, index)));
+            }
+            Flow::Jump(target) => pending.push((target, index)),
+            Flow::Call(target) => {
+                // The subroutine may change I before it returns
+                pending.push((target, index));
+                pending.extend(program.next(at).map(|next| (next, None)));
+            }
+            Flow::Return | Flow::Stop => {}
+        }
+    }
+
+    // Each instruction once, however many values of I reached it
+    for &at in &analysis.code {
+        let Some(opcode) = program.opcode(at) else {
+            continue;
+        };
+        let instruction = Instruction::decode(opcode);
+
+        match classify(instruction) {
+            Some((Family::Chip8, _)) => {}
+            Some((family, pattern)) => {
+                analysis.extensions.entry(family).or_default().insert(pattern);
+            }
+            None => {
+                analysis.unknown.insert(at, opcode);
+            }
+        }
+
+        if let Some((pattern, quirks)) = quirk_sensitive(instruction) {
+            analysis.quirky.entry(pattern).or_insert((0, quirks)).0 += 1;
+        }
+
+        if let Instruction::JumpV0(_) = instruction {
+            analysis.computed_jumps += 1;
+        }
+    }
+
+    analysis.high_accesses.sort_by_key(|access| (access.at, access.start));
+    analysis.high_accesses.dedup();
+
+    analysis.call_depth = CallGraph::new(&program).depth(START);
+
+    Ok(analysis)
+}
+
+struct Program<'a> {
+    rom: &'a [u8],
+}
+
+impl Program<'_> {
+    // The opcode at `at`, None outside of the ROM
+    fn opcode(&self, at: u16) -> Option<u16> {
+        let offset = at.checked_sub(START)? as usize;
+        let bytes = self.rom.get(offset..offset + 2)?;
+
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    // XO-CHIP's F000 NNNN is the only instruction with two words
+    fn size(&self, at: u16) -> u16 {
+        match self.opcode(at) {
+            Some(0xF000) => 4,
+            _ => 2,
+        }
+    }
+
+    // Address of the instruction after the one at `at`, None when it would
+    // be past the end of memory
+    fn next(&self, at: u16) -> Option<u16> {
+        at.checked_add(self.size(at))
+            .filter(|&next| (next as usize) < MEMORY_SIZE)
+    }
+}
+
+fn flow(instruction: Instruction, at: u16) -> Flow {
+    match instruction {
+        // Programs often end by jumping to themselves
+        Instruction::Jump(nnn) if nnn == at => Flow::Stop,
+        Instruction::Jump(nnn) => Flow::Jump(nnn),
+        Instruction::Call(nnn) => Flow::Call(nnn),
+        Instruction::Ret => Flow::Return,
+        Instruction::SkipEqImm(..)
+        | Instruction::SkipNeImm(..)
+        | Instruction::SkipEqReg(..)
+        | Instruction::SkipNeReg(..)
+        | Instruction::SkipKey(_)
+        | Instruction::SkipNoKey(_) => Flow::Skip,
+        Instruction::JumpV0(_) => Flow::Stop,
+        // 00FD exits the SUPER-CHIP interpreter
+        Instruction::Sys(0x0FD) => Flow::Stop,
+        // The CPU stalls on opcodes no interpreter knows
+        Instruction::Unknown(_) if classify(instruction).is_none() => Flow::Stop,
+        _ => Flow::Next,
+    }
+}
+
+// The family an instruction belongs to, with the opcode pattern for the
+// extensions. None for opcodes no interpreter knows.
+pub fn classify(instruction: Instruction) -> Option<(Family, &'static str)> {
+    let superchip = |pattern| Some((Family::SuperChip, pattern));
+    let xochip = |pattern| Some((Family::XoChip, pattern));
+
+    match instruction {
+        Instruction::Sys(nnn) => match nnn {
+            0x0C0..=0x0CF => superchip("00CN"),
+            0x0D0..=0x0DF => xochip("00DN"),
+            0x0FB => superchip("00FB"),
+            0x0FC => superchip("00FC"),
+            0x0FD => superchip("00FD"),
+            0x0FE => superchip("00FE"),
+            0x0FF => superchip("00FF"),
+            _ => Some((Family::Chip8, "0NNN")),
+        },
+        Instruction::Draw(_, _, 0) => superchip("DXY0"),
+        Instruction::Unknown(opcode) => match (opcode & 0xF000, opcode & 0x00FF) {
+            (0x5000, nn) if nn & 0xF == 0x2 => xochip("5XY2"),
+            (0x5000, nn) if nn & 0xF == 0x3 => xochip("5XY3"),
+            (0xF000, 0x00) if opcode == 0xF000 => xochip("F000"),
+            (0xF000, 0x01) => xochip("FN01"),
+            (0xF000, 0x02) if opcode == 0xF002 => xochip("F002"),
+            (0xF000, 0x30) => superchip("FX30"),
+            (0xF000, 0x3A) => xochip("FX3A"),
+            (0xF000, 0x75) => superchip("FX75"),
+            (0xF000, 0x85) => superchip("FX85"),
+            _ => None,
+        },
+        _ => Some((Family::Chip8, "")),
+    }
+}
+
+// Opcodes that behave differently depending on the quirks, with the quirks
+fn quirk_sensitive(instruction: Instruction) -> Option<(&'static str, &'static str)> {
+    match instruction {
+        Instruction::Or(..) => Some(("8XY1", "logic")),
+        Instruction::And(..) => Some(("8XY2", "logic")),
+        Instruction::Xor(..) => Some(("8XY3", "logic")),
+        Instruction::ShiftRight(..) => Some(("8XY6", "shift")),
+        Instruction::ShiftLeft(..) => Some(("8XYE", "shift")),
+        Instruction::JumpV0(_) => Some(("BNNN", "jump")),
+        Instruction::Draw(..) => Some(("DXYN", "wrap, vblank")),
+        Instruction::WaitKey(_) => Some(("FX0A", "key_wait_release")),
+        Instruction::Store(_) => Some(("FX55", "memory_increment_by_x, memory_leave_i_unchanged")),
+        Instruction::Load(_) => Some(("FX65", "memory_increment_by_x, memory_leave_i_unchanged")),
+        _ => None,
+    }
+}
+
+// Memory an instruction reads or writes from I on, with its length
+fn memory_access(instruction: Instruction, opcode: u16) -> Option<(&'static str, Access, u16)> {
+    match instruction {
+        // DXY0 draws a 16x16 sprite on SUPER-CHIP
+        Instruction::Draw(_, _, 0) => Some(("DXY0", Access::Read, 32)),
+        Instruction::Draw(_, _, n) => Some(("DXYN", Access::Read, n as u16)),
+        Instruction::StoreBcd(_) => Some(("FX33", Access::Write, 3)),
+        Instruction::Store(x) => Some(("FX55", Access::Write, x as u16 + 1)),
+        Instruction::Load(x) => Some(("FX65", Access::Read, x as u16 + 1)),
+        Instruction::Unknown(_) if opcode & 0xF00F == 0x5002 || opcode & 0xF00F == 0x5003 => {
+            let x = (opcode >> 8) & 0xF;
+            let y = (opcode >> 4) & 0xF;
+            let length = x.abs_diff(y) + 1;
+
+            if opcode & 0xF == 0x2 {
+                Some(("5XY2", Access::Write, length))
+            } else {
+                Some(("5XY3", Access::Read, length))
+            }
+        }
+        _ => None,
+    }
+}
+
+// Subroutines and the subroutines they call, for the call depth
+struct CallGraph {
+    calls: HashMap<u16, BTreeSet<u16>>,
+}
+
+impl CallGraph {
+    fn new(program: &Program) -> Self {
+        let mut graph = CallGraph {
+            calls: HashMap::new(),
+        };
+        let mut pending = vec![START];
+
+        while let Some(entry) = pending.pop() {
+            if graph.calls.contains_key(&entry) {
+                continue;
+            }
+
+            let callees = Self::callees(program, entry);
+            pending.extend(callees.iter().copied());
+            graph.calls.insert(entry, callees);
+        }
+
+        graph
+    }
+
+    // Everything called from the code reachable from `entry` up to its returns
+    fn callees(program: &Program, entry: u16) -> BTreeSet<u16> {
+        let mut callees = BTreeSet::new();
+        let mut seen = HashSet::new();
+        let mut pending = vec![entry];
+
+        while let Some(at) = pending.pop() {
+            if !seen.insert(at) {
+                continue;
+            }
+
+            let Some(opcode) = program.opcode(at) else {
+                continue;
+            };
+
+            match flow(Instruction::decode(opcode), at) {
+                Flow::Next => pending.extend(program.next(at)),
+                Flow::Skip => {
+                    let next = program.next(at);
+                    pending.extend(next);
+                    pending.extend(next.and_then(|next| program.next(next)));
+                }
+                Flow::Jump(target) => pending.push(target),
+                Flow::Call(target) => {
+                    callees.insert(target);
+                    pending.extend(program.next(at));
+                }
+                Flow::Return | Flow::Stop => {}
+            }
+        }
+
+        callees
+    }
+
+    // Calls nested below `entry`, None when they recurse
+    fn depth(&self, entry: u16) -> Option<usize> {
+        self.depth_from(entry, &mut Vec::new(), &mut HashMap::new())
+    }
+
+    fn depth_from(
+        &self,
+        entry: u16,
+        path: &mut Vec<u16>,
+        known: &mut HashMap<u16, Option<usize>>,
+    ) -> Option<usize> {
+        if path.contains(&entry) {
+            return None;
+        }
+
+        if let Some(&depth) = known.get(&entry) {
+            return depth;
+        }
+
+        path.push(entry);
+
+        let mut depth = Some(0);
+
+        for &callee in self.calls.get(&entry).into_iter().flatten() {
+            depth = match (depth, self.depth_from(callee, path, known)) {
+                (Some(depth), Some(below)) => Some(depth.max(below + 1)),
+                _ => None,
+            };
+        }
+
+        path.pop();
+        known.insert(entry, depth);
+
+        depth
+    }
+}
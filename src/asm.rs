@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use crate::instruction::{Instruction, Operand};
+
+// Two pass assembler for the mnemonics of instruction.rs. The syntax is what
+// `disasm` prints, so a disassembly assembles back into the same ROM:
+//
+//   ; comments run to the end of the line
+//   start:  LD V0, 0x05     ; labels end with a colon
+//           CALL draw       ; and can be used wherever an address goes
+//   loop:   JP loop
+//   draw:   DRW V0, V1, 5
+//           RET
+//   sprite: DB 0xF0, 0x90, 0b11110000
+//           DW 0x1234
+//
+// Numbers are decimal, or hex with a 0x, # or $ prefix, or binary with 0b.
+
+// Where CHIP-8 programs are loaded
+pub const ORIGIN: u16 = 0x200;
+
+struct Line<'a> {
+    number: usize,
+    mnemonic: &'a str,
+    operands: Vec<&'a str>,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    let mut address = ORIGIN as usize;
+
+    // First pass, where every label ends up
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let error = |e: String| format!("line {}: {}", number, e);
+
+        let mut text = text.split(';').next().unwrap_or("").trim();
+
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+
+            if !is_label(label) {
+                return Err(error(format!("invalid label '{}'", label)));
+            }
+            if labels.insert(label.to_lowercase(), address as u16).is_some() {
+                return Err(error(format!("label '{}' is defined twice", label)));
+            }
+
+            text = rest.trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, operands.split(',').map(str::trim).collect()),
+            None => (text, Vec::new()),
+        };
+
+        address += match mnemonic.to_uppercase().as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            _ => 2,
+        };
+
+        if address > 0x1000 {
+            return Err(error("the program does not fit in memory".to_owned()));
+        }
+
+        lines.push(Line {
+            number,
+            mnemonic,
+            operands,
+        });
+    }
+
+    // Second pass, now that every label is known
+    let mut rom = Vec::new();
+
+    for line in lines {
+        let error = |e: String| format!("line {}: {}", line.number, e);
+
+        let operands = line
+            .operands
+            .iter()
+            .map(|operand| parse_operand(operand, &labels))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error)?;
+
+        match line.mnemonic.to_uppercase().as_str() {
+            "DB" | "DW" if operands.is_empty() => {
+                return Err(error(format!("{} needs at least one value", line.mnemonic)));
+            }
+            "DB" => {
+                for operand in operands {
+                    match operand {
+                        Operand::Number(value) if value <= 0xFF => rom.push(value as u8),
+                        _ => return Err(error("DB takes byte values".to_owned())),
+                    }
+                }
+            }
+            "DW" => {
+                for operand in operands {
+                    match operand {
+                        Operand::Number(value) => rom.extend_from_slice(&value.to_be_bytes()),
+                        _ => return Err(error("DW takes 16 bit values".to_owned())),
+                    }
+                }
+            }
+            _ => {
+                let instruction = Instruction::parse(line.mnemonic, &operands).map_err(error)?;
+                rom.extend_from_slice(&instruction.encode().to_be_bytes());
+            }
+        }
+    }
+
+    Ok(rom)
+}
+
+fn parse_operand(text: &str, labels: &HashMap<String, u16>) -> Result<Operand, String> {
+    let upper = text.to_uppercase();
+
+    let operand = match upper.as_str() {
+        "I" => Operand::Index,
+        "[I]" => Operand::IndexMemory,
+        "DT" => Operand::Delay,
+        "ST" => Operand::Sound,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "B" => Operand::Bcd,
+        "PITCH" => Operand::Pitch,
+        _ => {
+            if let Some(register) = upper
+                .strip_prefix('V')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| u8::from_str_radix(digit, 16).ok())
+            {
+                Operand::Register(register)
+            } else if let Some(value) = parse_number(&upper) {
+                Operand::Number(value?)
+            } else if let Some(&address) = labels.get(&text.to_lowercase()) {
+                Operand::Number(address)
+            } else if is_label(text) {
+                return Err(format!("unknown label '{}'", text));
+            } else {
+                return Err(format!("invalid operand '{}'", text));
+            }
+        }
+    };
+
+    Ok(operand)
+}
+
+// None when the text does not look like a number at all
+fn parse_number(text: &str) -> Option<Result<u16, String>> {
+    let (digits, radix) = if let Some(hex) = text
+        .strip_prefix("0X")
+        .or_else(|| text.strip_prefix('#'))
+        .or_else(|| text.strip_prefix('$'))
+    {
+        (hex, 16)
+    } else if let Some(binary) = text.strip_prefix("0B") {
+        (binary, 2)
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        (text, 10)
+    } else {
+        return None;
+    };
+
+    Some(u16::from_str_radix(digits, radix).map_err(|_| format!("invalid number '{}'", text)))
+}
+
+fn is_label(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,169 @@
+use std::{f32::consts::TAU, str::FromStr};
+
+#[cfg(feature = "sdl")]
+mod sdl;
+mod wav;
+
+#[cfg(feature = "sdl")]
+pub use sdl::SdlSink;
+pub use wav::WavSink;
+
+pub const SAMPLE_RATE: u32 = 44_100;
+
+// Names of the sound outputs the emulator can open, in the order the F2
+// hotkey goes through them
+pub const OUTPUTS: [&str; 3] = ["sdl", "wav", "null"];
+
+// Samples generated for every emulated 60 Hz frame
+pub const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / 60;
+
+// Where the emulator loop sends its audio. Samples are generated from the
+// sound timer once per emulated frame (see SAMPLES_PER_FRAME), never from
+// wall time, so every sink receives the same stream at any emulation speed.
+pub trait AudioSink {
+    fn queue(&mut self, samples: &[f32]) -> Result<(), String>;
+
+    // Called once at the end of the session.
+    fn finish(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// Drops all samples, for headless runs and muted sessions.
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn queue(&mut self, _samples: &[f32]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+// Time it takes the tone to fade in or out. Starting or stopping a wave
+// abruptly makes an audible click.
+const RAMP_SECONDS: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            _ => Err(format!("unknown waveform '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneSettings {
+    pub waveform: Waveform,
+    pub frequency: f32, // Hz
+    pub volume: f32,    // 0.0 to 1.0
+}
+
+impl Default for ToneSettings {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+// XO-CHIP sound: 128 one-bit samples, the highest bit of the first byte
+// first, played in a loop while the sound timer runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pattern {
+    pub bits: [u8; 16],
+    pub pitch: u8,
+}
+
+impl Pattern {
+    // Bits per second, 4000 at the default pitch of 64 and an octave up or
+    // down for every 48 steps
+    pub fn rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.bits[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+}
+
+// Generates the buzzer tone played while the sound timer is running.
+#[derive(Debug, Clone)]
+pub struct ToneGenerator {
+    settings: ToneSettings,
+    sample_rate: u32,
+    phase: f32, // Position inside the current period or pattern, 0.0 to 1.0
+    gain: f32,
+}
+
+impl ToneGenerator {
+    pub fn new(settings: ToneSettings, sample_rate: u32) -> Self {
+        Self {
+            settings,
+            sample_rate,
+            phase: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    // Fills `out` with mono samples. When `active` changes the tone ramps in
+    // or out instead of starting or stopping at once. With a `pattern` that
+    // is played instead of the configured waveform and frequency.
+    pub fn fill(&mut self, out: &mut [f32], active: bool, pattern: Option<Pattern>) {
+        let target = if active { 1.0 } else { 0.0 };
+        let ramp_step = 1.0 / (self.sample_rate as f32 * RAMP_SECONDS);
+        let phase_step = match pattern {
+            Some(pattern) => pattern.rate() / 128.0 / self.sample_rate as f32,
+            None => self.settings.frequency / self.sample_rate as f32,
+        };
+
+        for sample in out.iter_mut() {
+            if self.gain < target {
+                self.gain = (self.gain + ramp_step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - ramp_step).max(target);
+            }
+
+            // Once fully silent the next tone starts at the beginning of a period
+            if self.gain == 0.0 {
+                *sample = 0.0;
+                self.phase = 0.0;
+                continue;
+            }
+
+            let wave = match (pattern, self.settings.waveform) {
+                (Some(pattern), _) => {
+                    if pattern.bit((self.phase * 128.0) as usize % 128) {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                (None, Waveform::Square) => {
+                    if self.phase < 0.5 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                (None, Waveform::Sine) => (self.phase * TAU).sin(),
+                (None, Waveform::Triangle) => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            };
+
+            *sample = wave * self.gain * self.settings.volume;
+            self.phase = (self.phase + phase_step).fract();
+        }
+    }
+}
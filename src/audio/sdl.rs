@@ -0,0 +1,43 @@
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+use super::{AudioSink, SAMPLES_PER_FRAME, SAMPLE_RATE};
+
+// Samples queued ahead of the device before new ones get dropped. When the
+// emulation runs faster than real time the audio would lag further and
+// further behind otherwise.
+const MAX_QUEUED_FRAMES: u32 = 4;
+
+// Plays the samples through the default SDL2 audio device.
+pub struct SdlSink {
+    queue: AudioQueue<f32>,
+}
+
+impl SdlSink {
+    pub fn new() -> Result<Self, String> {
+        let sdl = sdl2::init()?;
+        let audio = sdl.audio()?;
+
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: Some(512),
+        };
+
+        let queue = audio.open_queue::<f32, _>(None, &desired)?;
+        queue.resume();
+
+        Ok(Self { queue })
+    }
+}
+
+impl AudioSink for SdlSink {
+    fn queue(&mut self, samples: &[f32]) -> Result<(), String> {
+        let queued_samples = self.queue.size() / std::mem::size_of::<f32>() as u32;
+
+        if queued_samples > MAX_QUEUED_FRAMES * SAMPLES_PER_FRAME as u32 {
+            return Ok(());
+        }
+
+        self.queue.queue_audio(samples)
+    }
+}
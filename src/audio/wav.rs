@@ -0,0 +1,85 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::AudioSink;
+
+const HEADER_SIZE: u32 = 44;
+// The RIFF chunk size is a u32 and counts everything after its own 8 bytes,
+// which caps a recording at a little over 13 hours at 44.1 kHz
+const MAX_SAMPLES: u64 = (u32::MAX - (HEADER_SIZE - 8)) as u64 / 2;
+
+// Writes mono 16-bit PCM samples into a WAV file. The sizes in the header are
+// only known at the end, so they get patched in by finish(). Once the file is
+// full, the rest of the recording is dropped.
+pub struct WavSink {
+    out: BufWriter<File>,
+    samples: u64,
+}
+
+impl WavSink {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?; // RIFF chunk size, patched later
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&1u16.to_le_bytes())?; // Mono
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * 2).to_le_bytes())?; // Bytes per second
+        out.write_all(&2u16.to_le_bytes())?; // Bytes per frame
+        out.write_all(&16u16.to_le_bytes())?; // Bits per sample
+
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?; // Data size, patched later
+
+        Ok(Self { out, samples: 0 })
+    }
+
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let left = MAX_SAMPLES - self.samples;
+        if left > 0 && left <= samples.len() as u64 {
+            warn!("The WAV file is full, the rest of the sound is not recorded");
+        }
+
+        let room = left.min(samples.len() as u64) as usize;
+
+        for sample in &samples[..room] {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+
+        self.samples += room as u64;
+
+        Ok(())
+    }
+
+    fn patch_header(&mut self) -> io::Result<()> {
+        // Fits, write() stops at MAX_SAMPLES
+        let data_size = (self.samples * 2) as u32;
+
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+
+        self.out.flush()
+    }
+}
+
+impl AudioSink for WavSink {
+    fn queue(&mut self, samples: &[f32]) -> Result<(), String> {
+        self.write(samples).map_err(|e| e.to_string())
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.patch_header().map_err(|e| e.to_string())
+    }
+}
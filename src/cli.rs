@@ -0,0 +1,263 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use clap::{Args, Parser, Subcommand};
+
+use chip_8_emulator::{
+    audio, config::Settings, cpu::MAX_INSTRUCTIONS_PER_FRAME,
+    filters::{FilterChain, MAX_SCALE}, gpu::{Palette, ScaleMode, MAX_KEY_HOLD_MS},
+    keymap::Keymap, log::LogLevel, quirks::{Quirks, PROFILES}, rewind::MAX_REWIND_SECONDS,
+    speed::Speed,
+};
+
+#[cfg(feature = "sdl")]
+const DEFAULT_FRONTEND: &str = "sdl";
+#[cfg(not(feature = "sdl"))]
+const DEFAULT_FRONTEND: &str = "terminal";
+
+#[derive(Debug, Parser)]
+#[command(name = "chip-8-emulator", version, about = "A CHIP-8 emulator and toolkit")]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// How much to report on stderr: off, error, warn, info or debug
+    #[arg(long, global = true, value_name = "LEVEL", default_value = "warn")]
+    pub log_level: LogLevel,
+
+    /// Settings file to use instead of config.toml in the config directory
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Play a ROM in a window or in the terminal
+    Run(RunArgs),
+    /// Run a ROM without a display, as fast as possible
+    Headless(EmulatorArgs),
+    /// Print a ROM as assembly
+    Disasm {
+        rom: PathBuf,
+        /// Write the listing into this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Assemble a source file into a ROM
+    Asm {
+        source: PathBuf,
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// Show what is known about a ROM and which instructions and quirks it uses
+    Info { rom: PathBuf },
+    /// Run test suite ROMs under every quirks profile and score every check
+    Conformance(ConformanceArgs),
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ConformanceArgs {
+    /// ROMs of the test suite, recognised by their file names
+    #[arg(required = true)]
+    pub roms: Vec<PathBuf>,
+
+    /// Directory with the expected frames of the ibm and keypad tests
+    #[arg(long, value_name = "DIR", default_value = "conformance")]
+    pub expected: PathBuf,
+
+    /// Quirks profiles to run under, like "chip8,schip" [default: all]
+    #[arg(long, value_name = "PROFILES", value_delimiter = ',', value_parser = parse_profile)]
+    pub profiles: Vec<String>,
+
+    /// Save the frames of the tests without ticks and crosses (ibm, keypad),
+    /// to be checked by eye and kept as the expected ones
+    #[arg(long)]
+    pub bless: bool,
+}
+
+fn parse_profile(name: &str) -> Result<String, String> {
+    match Quirks::profile(name) {
+        Some(_) => Ok(name.to_owned()),
+        None => Err(format!(
+            "unknown quirks profile '{}', use one of {}",
+            name,
+            PROFILES.join(", ")
+        )),
+    }
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct RunArgs {
+    #[command(flatten)]
+    pub emulator: EmulatorArgs,
+
+    /// sdl, terminal or capture=<dir> to write every frame as a PNG
+    #[arg(long, value_name = "FRONTEND", default_value = DEFAULT_FRONTEND)]
+    pub frontend: Frontend,
+
+    /// Host pixels per CHIP-8 pixel, for the window and captures
+    #[arg(
+        long,
+        value_name = "FACTOR",
+        value_parser = clap::value_parser!(u32).range(1..=MAX_SCALE as i64)
+    )]
+    pub scale: Option<u32>,
+
+    /// How the window fits the frame: integer, aspect or stretch [default: integer]
+    #[arg(long, value_name = "MODE")]
+    pub scale_mode: Option<ScaleMode>,
+
+    /// white, amber, green, lcd, or lit and unlit colours as "RRGGBB,RRGGBB"
+    #[arg(long)]
+    pub palette: Option<Palette>,
+
+    /// Post-processing for the window, like "scanlines=0.4,bloom"
+    #[arg(long)]
+    pub filters: Option<FilterChain>,
+
+    /// Frames an erased pixel keeps glowing, 0 draws the raw framebuffer [default: 3]
+    #[arg(long, value_name = "FRAMES")]
+    pub phosphor: Option<u8>,
+
+    /// Whether the left stick of game controllers acts as the D-pad [default: true]
+    #[arg(long, value_name = "BOOL")]
+    pub stick_as_dpad: Option<bool>,
+
+    /// Milliseconds a key stays held in terminals without key releases [default: 600]
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        value_parser = clap::value_parser!(u64).range(1..=MAX_KEY_HOLD_MS)
+    )]
+    pub key_hold: Option<u64>,
+}
+
+impl RunArgs {
+    // The flags that override the settings file
+    pub fn settings(&mut self) -> Settings {
+        let mut settings = self.emulator.settings();
+
+        settings.palette = self.palette.take();
+        settings.filters = self.filters.take();
+        settings.scale = self.scale;
+        settings.scale_mode = self.scale_mode;
+        settings.phosphor = self.phosphor;
+        settings.stick_as_dpad = self.stick_as_dpad;
+        settings.key_hold = self.key_hold;
+
+        settings
+    }
+}
+
+// Everything about the emulated machine and its input and output, shared by
+// `run` and `headless`.
+#[derive(Debug, Args)]
+pub(crate) struct EmulatorArgs {
+    /// The CHIP-8 program to load
+    pub rom: PathBuf,
+
+    /// Quirks profile and overrides, like "chip8,key_wait_release=off"
+    #[arg(long)]
+    pub quirks: Option<Quirks>,
+
+    /// Keymap preset and overrides, like "azerty,Space=5"
+    #[arg(long)]
+    pub keymap: Option<Keymap>,
+
+    /// Seed for the random numbers of CXNN
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// CPU clock, in instructions per 60 Hz frame
+    #[arg(
+        long,
+        value_name = "INSTRUCTIONS",
+        value_parser = clap::value_parser!(u32).range(1..=MAX_INSTRUCTIONS_PER_FRAME as i64)
+    )]
+    pub ipf: Option<u32>,
+
+    /// Emulation speed: a multiplier like 0.5 or 4x, or unlimited
+    #[arg(long)]
+    pub speed: Option<Speed>,
+
+    /// Stop after this many frames
+    #[arg(long, value_name = "COUNT")]
+    pub frames: Option<u64>,
+
+    /// Sound output: sdl, wav to write <rom>-<frame>.wav, or null
+    #[arg(long, value_name = "OUTPUT", value_parser = audio::OUTPUTS)]
+    pub audio: Option<String>,
+
+    /// Also write the sound into this WAV file
+    #[arg(long, value_name = "FILE")]
+    pub wav: Option<PathBuf>,
+
+    /// Record the keypad input into this movie file
+    #[arg(long, value_name = "MOVIE", conflicts_with = "play")]
+    pub record: Option<PathBuf>,
+
+    /// Replay the keypad input of this movie file
+    #[arg(long, value_name = "MOVIE")]
+    pub play: Option<PathBuf>,
+
+    /// Seconds of history kept for rewinding, 0 turns it off [default: 10]
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = clap::value_parser!(u64).range(0..=MAX_REWIND_SECONDS)
+    )]
+    pub rewind_seconds: Option<u64>,
+}
+
+impl EmulatorArgs {
+    // The flags that override the settings file
+    pub fn settings(&mut self) -> Settings {
+        let mut settings = Settings::default();
+
+        settings.speed = self.speed;
+        settings.ipf = self.ipf;
+        settings.quirks = self.quirks;
+        settings.keymap = self.keymap.take();
+        settings.audio = self.audio.take();
+        settings.rewind_seconds = self.rewind_seconds;
+
+        settings
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Frontend {
+    Sdl,
+    Terminal,
+    // Writes PNGs into the directory
+    Capture(PathBuf),
+    Headless,
+}
+
+impl FromStr for Frontend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sdl" => Ok(Frontend::Sdl),
+            "terminal" => Ok(Frontend::Terminal),
+            "headless" => Ok(Frontend::Headless),
+            _ => match s.strip_prefix("capture=") {
+                Some(directory) if !directory.is_empty() => {
+                    Ok(Frontend::Capture(PathBuf::from(directory)))
+                }
+                _ => Err(format!("unknown frontend '{}'", s)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Frontend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Frontend::Sdl => write!(f, "sdl"),
+            Frontend::Terminal => write!(f, "terminal"),
+            Frontend::Capture(directory) => write!(f, "capture={}", directory.display()),
+            Frontend::Headless => write!(f, "headless"),
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::{collections::HashMap, env, fs, path::PathBuf, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{
+    audio::{self, Waveform}, cpu::MAX_INSTRUCTIONS_PER_FRAME,
+    filters::{FilterChain, MAX_SCALE}, gpu::{Palette, ScaleMode, MAX_KEY_HOLD_MS},
+    keymap::Keymap, quirks::Quirks, rewind::MAX_REWIND_SECONDS, speed::Speed,
+};
+
+// Settings file, config.toml in the XDG config directory. Top level keys
+// apply to every ROM, [rom.<sha1>] sections to the ROM with that hash only:
+//
+//   palette = "amber"
+//   keymap = "azerty"
+//
+//   [rom.5c28a5f85289c9d859f95fd5eadbdcb1c30bb08b]
+//   ipf = 15
+//   quirks = "chip8,key_wait_release=off"
+//
+// Command line flags win over the ROM section, which wins over the top level.
+// Values use the same syntax as the matching command line flags.
+
+const FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(default, deserialize_with = "parsed")]
+    pub speed: Option<Speed>,
+    #[serde(default, deserialize_with = "instructions_per_frame")]
+    pub ipf: Option<u32>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub quirks: Option<Quirks>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub keymap: Option<Keymap>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub palette: Option<Palette>,
+    // Sound output, sdl, wav or null
+    #[serde(default, deserialize_with = "audio_output")]
+    pub audio: Option<String>,
+    #[serde(default, deserialize_with = "parsed")]
+    pub waveform: Option<Waveform>,
+    // Buzzer pitch in Hz
+    #[serde(default, deserialize_with = "frequency")]
+    pub frequency: Option<f32>,
+    // Buzzer volume, 0.0 to 1.0
+    #[serde(default, deserialize_with = "volume")]
+    pub volume: Option<f32>,
+    // Post-processing for the window, see FilterChain::from_str
+    #[serde(default, deserialize_with = "parsed")]
+    pub filters: Option<FilterChain>,
+    #[serde(default, deserialize_with = "scale")]
+    pub scale: Option<u32>,
+    // How the window fits the frame: integer, aspect or stretch
+    #[serde(default, deserialize_with = "parsed")]
+    pub scale_mode: Option<ScaleMode>,
+    // Frames an erased pixel keeps glowing, 0 to 255
+    pub phosphor: Option<u8>,
+    // Whether the left stick of game controllers acts as the D-pad
+    pub stick_as_dpad: Option<bool>,
+    // Milliseconds a key counts as held in terminals that only report presses
+    #[serde(default, deserialize_with = "key_hold")]
+    pub key_hold: Option<u64>,
+    #[serde(default, deserialize_with = "rewind_seconds")]
+    pub rewind_seconds: Option<u64>,
+    // Only allowed at the top level, see Config::load
+    #[serde(default)]
+    rom: HashMap<String, Settings>,
+}
+
+impl Settings {
+    // Settings from `over` where it has them, from self otherwise.
+    pub fn layer(self, over: Settings) -> Settings {
+        Settings {
+            speed: over.speed.or(self.speed),
+            ipf: over.ipf.or(self.ipf),
+            quirks: over.quirks.or(self.quirks),
+            keymap: over.keymap.or(self.keymap),
+            palette: over.palette.or(self.palette),
+            audio: over.audio.or(self.audio),
+            waveform: over.waveform.or(self.waveform),
+            frequency: over.frequency.or(self.frequency),
+            volume: over.volume.or(self.volume),
+            filters: over.filters.or(self.filters),
+            scale: over.scale.or(self.scale),
+            scale_mode: over.scale_mode.or(self.scale_mode),
+            phosphor: over.phosphor.or(self.phosphor),
+            stick_as_dpad: over.stick_as_dpad.or(self.stick_as_dpad),
+            key_hold: over.key_hold.or(self.key_hold),
+            rewind_seconds: over.rewind_seconds.or(self.rewind_seconds),
+            rom: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    global: Settings,
+    // By lowercase SHA-1
+    roms: HashMap<String, Settings>,
+}
+
+impl Config {
+    // Reads the file at `path`, or the one in the config directory when no
+    // path is given. Only a missing default file is fine, it means defaults.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, String> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+
+        let mut global: Settings =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let roms: HashMap<String, Settings> = std::mem::take(&mut global.rom)
+            .into_iter()
+            .map(|(hash, settings)| (hash.to_lowercase(), settings))
+            .collect();
+
+        if let Some(hash) = roms.iter().find_map(|(hash, rom)| (!rom.rom.is_empty()).then_some(hash)) {
+            return Err(format!(
+                "{}: [rom.{}] can not contain other rom sections",
+                path.display(),
+                hash
+            ));
+        }
+
+        info!("Loaded settings from {}", path.display());
+
+        Ok(Config { global, roms })
+    }
+
+    // The top level settings with the section of the ROM on top.
+    pub fn for_rom(mut self, rom_hash: &str) -> Settings {
+        match self.roms.remove(rom_hash) {
+            Some(rom) => self.global.layer(rom),
+            None => self.global,
+        }
+    }
+}
+
+// $XDG_CONFIG_HOME/chip-8-emulator/config.toml, or ~/.config when unset
+fn default_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(base.join(env!("CARGO_PKG_NAME")).join(FILE_NAME))
+}
+
+// Values written as strings in the command line syntax, so errors still point
+// at the line they are on.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(de::Error::custom)
+}
+
+fn instructions_per_frame<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let count = u32::deserialize(deserializer)?;
+
+    if !(1..=MAX_INSTRUCTIONS_PER_FRAME as u32).contains(&count) {
+        return Err(de::Error::custom(format!(
+            "ipf must be between 1 and {}",
+            MAX_INSTRUCTIONS_PER_FRAME
+        )));
+    }
+
+    Ok(Some(count))
+}
+
+fn scale<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let scale = u32::deserialize(deserializer)?;
+
+    if !(1..=MAX_SCALE as u32).contains(&scale) {
+        return Err(de::Error::custom(format!(
+            "scale must be between 1 and {}",
+            MAX_SCALE
+        )));
+    }
+
+    Ok(Some(scale))
+}
+
+fn audio_output<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let output = String::deserialize(deserializer)?;
+
+    if !audio::OUTPUTS.contains(&output.as_str()) {
+        return Err(de::Error::custom(format!(
+            "unknown audio output '{}', expected {}",
+            output,
+            audio::OUTPUTS.join(" or ")
+        )));
+    }
+
+    Ok(Some(output))
+}
+
+fn frequency<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let frequency = f32::deserialize(deserializer)?;
+
+    // Past half the sample rate the tone can not be played back
+    if !(frequency > 0.0 && frequency <= audio::SAMPLE_RATE as f32 / 2.0) {
+        return Err(de::Error::custom(format!(
+            "frequency must be above 0 and at most {} Hz",
+            audio::SAMPLE_RATE / 2
+        )));
+    }
+
+    Ok(Some(frequency))
+}
+
+fn volume<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let volume = f32::deserialize(deserializer)?;
+
+    if !(0.0..=1.0).contains(&volume) {
+        return Err(de::Error::custom("volume must be between 0.0 and 1.0"));
+    }
+
+    Ok(Some(volume))
+}
+
+fn key_hold<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let milliseconds = u64::deserialize(deserializer)?;
+
+    if !(1..=MAX_KEY_HOLD_MS).contains(&milliseconds) {
+        return Err(de::Error::custom(format!(
+            "key_hold must be between 1 and {} milliseconds",
+            MAX_KEY_HOLD_MS
+        )));
+    }
+
+    Ok(Some(milliseconds))
+}
+
+fn rewind_seconds<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let seconds = u64::deserialize(deserializer)?;
+
+    if seconds > MAX_REWIND_SECONDS {
+        return Err(de::Error::custom(format!(
+            "rewind_seconds must be between 0 and {}",
+            MAX_REWIND_SECONDS
+        )));
+    }
+
+    Ok(Some(seconds))
+}
@@ -0,0 +1,422 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::Path,
+};
+
+use crate::{
+    cpu::{Framebuffer, CPU, SCREEN_HEIGHT, SCREEN_WIDTH},
+    quirks::Quirks,
+};
+
+// Runs the ROMs of the community CHIP-8 test suite
+// (https://github.com/Timendus/chip8-test-suite) without a display and
+// scores every run by its final framebuffer.
+//
+// The opcode, flags and quirks tests draw a tick or a cross next to each
+// thing they check, and every one of those marks is read off the screen.
+// The marks are the ones of corax89's opcode test, which ROMS/TEST.ch8 is
+// the original of. A ROM that draws them differently shows no marks at all,
+// which fails the check rather than passing it. The flags and quirks ROMs
+// have not been run yet to see whether they draw the same marks, so they
+// are marked unsupported and skipped.
+//
+// The IBM logo and the keypad test draw no marks. They are compared with
+// expected frames, PNGs written by a run with `bless` set, one per test,
+// mode and quirks profile. Until a blessed frame has been checked by eye
+// and kept, the check fails.
+
+// Where the suite looks for the menu choice, so the test starts right away
+const MENU_CHOICE: usize = 0x1FF;
+const SEED: u64 = 0;
+
+pub struct Test {
+    pub name: &'static str,
+    // Parts of the file names the suite has used for this ROM
+    pub file_names: &'static [&'static str],
+    pub frames: u64,
+    pub instructions_per_frame: usize,
+    pub scoring: Scoring,
+    pub modes: &'static [Mode],
+    // False until the scoring has been tried on the suite's own ROM
+    pub supported: bool,
+}
+
+// How a run of a test is judged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scoring {
+    // Ticks and crosses on the screen
+    Marks,
+    // The whole frame against an expected one
+    Frame,
+    // The buzzer has to sound at some point
+    Sound,
+}
+
+// One way of running a test, like the platform picked in its menu
+pub struct Mode {
+    // Empty for tests that only run one way
+    pub name: &'static str,
+    pub menu_choice: Option<u8>,
+    // Frame, CHIP-8 key and whether it goes down or up
+    pub input: &'static [(u64, u8, bool)],
+    // The quirks profile the mode checks for. Under any other profile its
+    // checks fail by design, so it only runs under this one.
+    pub profile: Option<&'static str>,
+}
+
+impl Mode {
+    pub fn runs_under(&self, profile: &str) -> bool {
+        self.profile.is_none_or(|own| own == profile)
+    }
+}
+
+const ONLY: &[Mode] = &[Mode {
+    name: "",
+    menu_choice: None,
+    input: &[],
+    profile: None,
+}];
+
+// Presses a few keys one after another, and holds two at once at the end
+const KEYPAD_INPUT: &[(u64, u8, bool)] = &[
+    (60, 0x1, true),
+    (70, 0x1, false),
+    (90, 0xA, true),
+    (100, 0xA, false),
+    (120, 0x5, true),
+    (120, 0xF, true),
+];
+
+pub const SUITE: [Test; 6] = [
+    Test {
+        name: "ibm",
+        file_names: &["ibm"],
+        frames: 60,
+        instructions_per_frame: 1000,
+        scoring: Scoring::Frame,
+        modes: ONLY,
+        supported: true,
+    },
+    // With the vblank quirk only one mark is drawn per frame
+    Test {
+        name: "corax",
+        file_names: &["corax"],
+        frames: 120,
+        instructions_per_frame: 1000,
+        scoring: Scoring::Marks,
+        modes: ONLY,
+        supported: true,
+    },
+    Test {
+        name: "flags",
+        file_names: &["flags"],
+        frames: 120,
+        instructions_per_frame: 1000,
+        scoring: Scoring::Marks,
+        modes: ONLY,
+        supported: false,
+    },
+    // The display wait check takes a few seconds of frames
+    Test {
+        name: "quirks",
+        file_names: &["quirks"],
+        frames: 600,
+        instructions_per_frame: 1000,
+        scoring: Scoring::Marks,
+        modes: &[
+            Mode {
+                name: "chip8",
+                menu_choice: Some(1),
+                input: &[],
+                profile: Some("chip8"),
+            },
+            Mode {
+                name: "schip",
+                menu_choice: Some(2),
+                input: &[],
+                profile: Some("schip"),
+            },
+            Mode {
+                name: "xochip",
+                menu_choice: Some(3),
+                input: &[],
+                profile: Some("xochip"),
+            },
+        ],
+        supported: false,
+    },
+    Test {
+        name: "keypad",
+        file_names: &["keypad"],
+        frames: 180,
+        instructions_per_frame: 1000,
+        scoring: Scoring::Frame,
+        modes: &[
+            Mode {
+                name: "ex9e",
+                menu_choice: Some(1),
+                input: KEYPAD_INPUT,
+                profile: None,
+            },
+            Mode {
+                name: "exa1",
+                menu_choice: Some(2),
+                input: KEYPAD_INPUT,
+                profile: None,
+            },
+            Mode {
+                name: "fx0a",
+                menu_choice: Some(3),
+                input: KEYPAD_INPUT,
+                profile: None,
+            },
+        ],
+        supported: true,
+    },
+    // Beeps for as long as B is held
+    Test {
+        name: "beep",
+        file_names: &["beep"],
+        frames: 120,
+        instructions_per_frame: 1000,
+        scoring: Scoring::Sound,
+        modes: &[Mode {
+            name: "",
+            menu_choice: None,
+            input: &[(60, 0xB, true), (90, 0xB, false)],
+            profile: None,
+        }],
+        supported: true,
+    },
+];
+
+// The test a ROM file is for, going by its name
+pub fn identify(path: &Path) -> Option<&'static Test> {
+    let file_name = path.file_name()?.to_string_lossy().to_lowercase();
+
+    SUITE
+        .iter()
+        .find(|test| test.file_names.iter().any(|name| file_name.contains(name)))
+}
+
+// Name of a check in the matrix and of its expected frame
+pub fn check_name(test: &Test, mode: &Mode, profile: &str) -> String {
+    if mode.name.is_empty() {
+        format!("{}-{}", test.name, profile)
+    } else {
+        format!("{}-{}-{}", test.name, mode.name, profile)
+    }
+}
+
+pub struct Run {
+    pub framebuffer: Framebuffer,
+    pub sound: bool,
+    // Why the program stopped early
+    pub error: Option<String>,
+}
+
+pub fn run(test: &Test, mode: &Mode, rom: &[u8], quirks: Quirks) -> Result<Run, String> {
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    cpu.load_program(rom)?;
+    cpu.reseed(SEED);
+
+    // Whatever the ROM database says, the test runs as asked
+    cpu.quirks = quirks;
+    cpu.instructions_per_frame = test.instructions_per_frame;
+
+    if let Some(choice) = mode.menu_choice {
+        cpu.game_memory[MENU_CHOICE] = choice;
+    }
+
+    let mut sound = false;
+    let mut error = None;
+
+    for frame in 0..test.frames {
+        for &(_, key, pressed) in mode.input.iter().filter(|(at, ..)| *at == frame) {
+            if pressed {
+                cpu.key_down(key);
+            } else {
+                cpu.key_up(key);
+            }
+        }
+
+        if let Err(e) = cpu.run_frame() {
+            error = Some(e);
+            break;
+        }
+
+        sound |= cpu.sound_active();
+    }
+
+    Ok(Run {
+        framebuffer: cpu.framebuffer,
+        sound,
+        error,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Fail(String),
+}
+
+// `expected` is only looked at for tests scored by their frame.
+pub fn score(test: &Test, run: &Run, expected: Option<&Framebuffer>) -> Verdict {
+    if let Some(error) = &run.error {
+        return Verdict::Fail(format!("stopped at {}", error));
+    }
+
+    match test.scoring {
+        Scoring::Marks => score_marks(&read_marks(&run.framebuffer)),
+        Scoring::Frame => match expected {
+            Some(expected) => score_frame(&run.framebuffer, expected),
+            None => Verdict::Fail("no expected frame".to_owned()),
+        },
+        Scoring::Sound if run.sound => Verdict::Pass,
+        Scoring::Sound => Verdict::Fail("never beeped".to_owned()),
+    }
+}
+
+fn score_marks(marks: &Marks) -> Verdict {
+    let checks = marks.ticks.len() + marks.crosses.len();
+    let at = |positions: &[(usize, usize)]| -> Vec<String> {
+        positions
+            .iter()
+            .map(|(x, y)| format!("{},{}", x, y))
+            .collect()
+    };
+
+    if checks == 0 {
+        Verdict::Fail("no ticks or crosses on the screen".to_owned())
+    } else if marks.crosses.is_empty() {
+        Verdict::Pass
+    } else {
+        Verdict::Fail(format!(
+            "{} of {} checks show a cross, at {}",
+            marks.crosses.len(),
+            checks,
+            at(&marks.crosses).join(" ")
+        ))
+    }
+}
+
+fn score_frame(actual: &Framebuffer, expected: &Framebuffer) -> Verdict {
+    match actual
+        .iter()
+        .zip(expected)
+        .filter(|(actual, expected)| actual != expected)
+        .count()
+    {
+        0 => Verdict::Pass,
+        1 => Verdict::Fail("1 pixel differs".to_owned()),
+        different => Verdict::Fail(format!("{} pixels differ", different)),
+    }
+}
+
+// Rows of the 3x3 marks, the leftmost pixel in the highest bit
+const TICK: [u8; 3] = [0b101, 0b110, 0b100];
+const CROSS: [u8; 3] = [0b101, 0b010, 0b101];
+
+// Top left corners of the marks on a screen, in reading order
+#[derive(Debug, Default, PartialEq)]
+pub struct Marks {
+    pub ticks: Vec<(usize, usize)>,
+    pub crosses: Vec<(usize, usize)>,
+}
+
+// Finds the marks by their shape. A mark stands on its own with unlit pixels
+// all around, which keeps letters like the X of "3X" from counting.
+pub fn read_marks(framebuffer: &Framebuffer) -> Marks {
+    let lit = |x: isize, y: isize| {
+        (0..SCREEN_WIDTH as isize).contains(&x)
+            && (0..SCREEN_HEIGHT as isize).contains(&y)
+            && framebuffer[y as usize * SCREEN_WIDTH + x as usize]
+    };
+
+    let mut marks = Marks::default();
+
+    for y in 0..SCREEN_HEIGHT - 2 {
+        for x in 0..SCREEN_WIDTH - 2 {
+            let (left, top) = (x as isize, y as isize);
+
+            // The 5x5 box around the mark, as rows of bits
+            let rows: Vec<u8> = (top - 1..top + 4)
+                .map(|y| (left - 1..left + 4).fold(0, |row, x| row << 1 | lit(x, y) as u8))
+                .collect();
+
+            if rows[0] != 0 || rows[4] != 0 || rows.iter().any(|row| row & 0b10001 != 0) {
+                continue;
+            }
+
+            let glyph = [rows[1] >> 1, rows[2] >> 1, rows[3] >> 1];
+
+            if glyph == TICK {
+                marks.ticks.push((x, y));
+            } else if glyph == CROSS {
+                marks.crosses.push((x, y));
+            }
+        }
+    }
+
+    marks
+}
+
+// One byte per pixel, white for lit
+pub fn save_frame(path: &Path, framebuffer: &Framebuffer) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(|e| error(&e))?;
+    }
+
+    let file = File::create(path).map_err(|e| error(&e))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        SCREEN_WIDTH as u32,
+        SCREEN_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let pixels: Vec<u8> = framebuffer
+        .iter()
+        .map(|&lit| if lit { 0xFF } else { 0x00 })
+        .collect();
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|e| error(&e))
+}
+
+// A frame written by save_frame, None when there is no file
+pub fn load_frame(path: &Path) -> Result<Option<Framebuffer>, String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(error(&e)),
+    };
+
+    let mut reader = png::Decoder::new(file).read_info().map_err(|e| error(&e))?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(|e| error(&e))?;
+
+    if (info.width as usize, info.height as usize, info.color_type)
+        != (SCREEN_WIDTH, SCREEN_HEIGHT, png::ColorType::Grayscale)
+    {
+        return Err(error(&"not a frame written by the conformance runner"));
+    }
+
+    let mut framebuffer = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+    for (lit, &pixel) in framebuffer.iter_mut().zip(&pixels) {
+        *lit = pixel >= 0x80;
+    }
+
+    Ok(Some(framebuffer))
+}
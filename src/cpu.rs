@@ -1,16 +1,38 @@
-use std::{
-    fs::File,
-    io::{Read, Seek, SeekFrom},
+use std::{fs, path::Path};
+
+use rand::Rng;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    database::{self, RomInfo},
+    dirty::DirtyRegion,
+    input::Keypad,
+    instruction::Instruction,
+    audio::Pattern,
+    opcodes::*,
+    quirks::Quirks,
 };
 
-use crate::{opcodes::*};
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+
+// Instructions executed between two 60 Hz timer ticks, unless the ROM or the
+// user asks for another clock speed
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 10;
 
-pub(crate) const SCREEN_WIDTH: usize = 64;
-pub(crate) const SCREEN_HEIGHT: usize = 32;
+// Highest clock the F4 hotkey and --ipf go up to
+pub const MAX_INSTRUCTIONS_PER_FRAME: usize = 1000;
 
+#[allow(clippy::upper_case_acronyms)]
 type BYTE = u8;
 
-type Framebuffer = [bool; SCREEN_WIDTH * SCREEN_HEIGHT];
+// XO-CHIP pitch register value that plays a pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+// 4 KiB, programs are loaded at 0x200
+pub const MEMORY_SIZE: usize = 0x1000;
+
+pub type Framebuffer = [bool; SCREEN_WIDTH * SCREEN_HEIGHT];
 
 const CHIP8_FONTSET:[u8;80] =
 [ 
@@ -32,9 +54,46 @@ const CHIP8_FONTSET:[u8;80] =
   0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+// xorshift64* generator. Unlike the rand generators its whole state is a
+// single number, so it fits in a save state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Random {
+    pub state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 spreads the seed bits and keeps the state from being 0
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+        Self {
+            state: (z ^ (z >> 31)).max(1),
+        }
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+// FX0A blocks until a key is pressed and, depending on the quirks, released.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyWait {
+    Idle,
+    Waiting,
+    Pressed(u8),
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug)]
-pub(crate) struct CPU {
-    pub game_memory: [BYTE; 0xFFF], //PROGRAM RAM
+pub struct CPU {
+    pub game_memory: [BYTE; MEMORY_SIZE], //PROGRAM RAM
     pub registers: [BYTE; 16],
     pub index_register: u16,
     pub program_counter: u16, //MEMORY POINTER
@@ -42,15 +101,34 @@ pub(crate) struct CPU {
     pub stack_pointer: u16,
     pub cur_opcode: u16,
     pub framebuffer:Framebuffer,
-    pub key: [u8;16],
+    pub keypad: Keypad,
+    pub key_wait: KeyWait, // Progress of a running FX0A
+    pub vblank_wait: bool, // A sprite was drawn this frame, see Quirks::vblank
     pub delay_timer: BYTE,
-    pub sound_timer: BYTE
+    pub sound_timer: BYTE,
+    pub audio_pattern: Option<[BYTE; 16]>, // Loaded by F002, the buzzer plays until then
+    pub pitch: BYTE,                       // Set by FX3A
+    pub frame_count: u64,
+    pub dirty: DirtyRegion, // Framebuffer changes since the last presented frame
+    pub quirks: Quirks,
+    pub instructions_per_frame: usize,
+    pub rom_hash: String, // SHA-1 of the loaded ROM, in hex
+    pub seed: u64,
+    pub rng: Random, // Used by CXNN, seeded so runs can be replayed
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CPU {
     pub fn new() -> Self {
+        let seed = rand::thread_rng().gen();
+
         Self {
-            game_memory: [Default::default(); 0xFFF],
+            game_memory: [Default::default(); MEMORY_SIZE],
             registers: [Default::default(); 16],
             index_register: 0x000000,
             program_counter: 0,
@@ -58,12 +136,28 @@ impl CPU {
             stack_pointer: 0x000000,
             cur_opcode: 0x000000,
             framebuffer: [Default::default(); SCREEN_WIDTH * SCREEN_HEIGHT],
-            key: [Default::default(); 16],
+            keypad: Keypad::default(),
+            key_wait: KeyWait::Idle,
+            vblank_wait: false,
             delay_timer: 0,
             sound_timer: 0,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            frame_count: 0,
+            dirty: DirtyRegion::default(),
+            quirks: Quirks::default(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            rom_hash: String::new(),
+            seed,
+            rng: Random::new(seed),
         }
     }
 
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Random::new(seed);
+    }
+
     pub fn initialize(&mut self) {
         self.index_register = 0;
         self.program_counter = 0x200;
@@ -73,26 +167,51 @@ impl CPU {
         self.stack = [Default::default(); 16];
         self.registers = unsafe { std::mem::zeroed() };
 
-        for i in 0..CHIP8_FONTSET.len() {
-            self.game_memory[i] = CHIP8_FONTSET[i];
-        }
+        self.dirty.mark_all(SCREEN_WIDTH, SCREEN_HEIGHT);
+
+        self.game_memory[..CHIP8_FONTSET.len()].copy_from_slice(&CHIP8_FONTSET);
     }
 
-    pub fn load_rom(&mut self, path: String) {
-        let mut file: File = File::open(path).expect("Failed to open the file!");
+    // Loads the ROM and applies the settings the ROM database recommends for
+    // it, which are returned too. None when the ROM is unknown.
+    pub fn load_rom(&mut self, path: &Path) -> Result<Option<RomInfo>, String> {
+        let buffer = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        self.load_program(&buffer)
+            .map_err(|e| format!("{} {}", path.display(), e))
+    }
 
-        let mut buffer: Vec<u8> = Vec::new();
+    // Like load_rom, for a ROM that is already in memory.
+    pub fn load_program(&mut self, rom: &[u8]) -> Result<Option<RomInfo>, String> {
+        let start = 0x200;
+        let end = 0x200 + rom.len();
+
+        if end > self.game_memory.len() {
+            return Err(format!(
+                "is {} bytes, a CHIP-8 program can be at most {}",
+                rom.len(),
+                self.game_memory.len() - start
+            ));
+        }
 
-        file.read_to_end(&mut buffer)
-            .expect("Failed to read the file!");
+        self.game_memory[start..end].copy_from_slice(rom);
+        self.rom_hash = rom_hash(rom);
 
-        let start = 0x200 as usize;
-        let end = 0x200 + buffer.len() as usize;
+        let rom_info = database::lookup(&self.rom_hash);
 
-        self.game_memory[start..end].copy_from_slice(&buffer)
+        if let Some(rom_info) = &rom_info {
+            self.quirks = rom_info.quirks;
+            self.instructions_per_frame = rom_info.instructions_per_frame;
+        }
+
+        Ok(rom_info)
     }
 
-    pub fn get_next_opcode(&mut self) {
+    pub fn get_next_opcode(&mut self) -> Result<(), String> {
+        if self.program_counter as usize + 1 >= MEMORY_SIZE {
+            return Err("the program ran off the end of memory".to_owned());
+        }
+
         self.cur_opcode = self.game_memory[self.program_counter as usize] as u16;
 
         self.cur_opcode =
@@ -104,155 +223,150 @@ impl CPU {
         //println!("{:?}", self.cur_opcode);
         //println!("0x{:X}", self.cur_opcode);
         //println!("{:016b}", self.cur_opcode);
+        Ok(())
+    }
+
+    // Frontends report host input through these, see Keymap.
+    pub fn key_down(&mut self, key: u8) {
+        self.keypad.key_down(key);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.keypad.key_up(key);
+    }
+
+    // Hands the changed region over to the frontend and starts tracking anew.
+    pub fn take_dirty(&mut self) -> DirtyRegion {
+        std::mem::take(&mut self.dirty)
     }
 
     pub fn get_delay(&self) -> BYTE {
-        return self.delay_timer;
+        self.delay_timer
     }
 
-    pub fn update(&mut self) {
-        self.get_next_opcode();
-
-        match self.cur_opcode & 0xF000 {
-            //Gets the first value of an opcode (Ex: From: 0x1234 gets 1);
-            0x0000 => match self.cur_opcode & 0x000F {
-                0x0000 => {
-                    self.framebuffer = [Default::default(); SCREEN_WIDTH * SCREEN_HEIGHT];
-                    self.program_counter += 2
-                },
-                0x000E => {
-                    opcode_0_0ee(self);
-                    self.program_counter += 2;
-                },
-                _ => {}
-            },
-            0x1000 => opcode_1_nnn(self, self.cur_opcode),
-            0x2000 => opcode_2_nnn(self, self.cur_opcode),
-            0x3000 => opcode_3_xnn(self, self.cur_opcode),
-            0x4000 => opcode_4_xnn(self, self.cur_opcode),
-            0x5000 => opcode_5_xy0(self, self.cur_opcode),
-            0x6000 => {
-                opcode_6_xnn(self, self.cur_opcode);
-                self.program_counter += 2;
-            }
-            0x7000 => {
-                opcode_7_xnn(self, self.cur_opcode);
-                self.program_counter += 2;
-            }
-            0x8000 => {
-                match self.cur_opcode & 0x000F {
-                    0x0000 => {
-                        opcode_8_xy0(self, self.cur_opcode);
-                    }
-                    0x0001 => {
-                        opcode_8_xy1(self, self.cur_opcode);
-                    }
-                    0x0002 => {
-                        opcode_8_xy2(self, self.cur_opcode);
-                    }
-                    0x0003 => {
-                        opcode_8_xy3(self, self.cur_opcode);
-                    }
-                    0x0004 => {
-                        opcode_8_xy4(self, self.cur_opcode);
-                    }
-                    0x0005 => {
-                        opcode_8_xy5(self, self.cur_opcode);
-                    }
-                    0x0006 => {
-                        opcode_8_xy6(self, self.cur_opcode);
-                    }
-                    0x0007 => {
-                        opcode_8_xy7(self, self.cur_opcode);
-                    }
-                    0x000E => {
-                        opcode_8_xye(self, self.cur_opcode);
-                    }
-                    _ => {}
-                }
+    // Runs one 60 Hz frame worth of instructions and ticks the timers once.
+    // When this returns the framebuffer holds a finished frame. On an error
+    // the machine is left at the instruction that failed.
+    pub fn run_frame(&mut self) -> Result<(), String> {
+        self.vblank_wait = false;
 
-                self.program_counter += 2;
+        for _ in 0..self.instructions_per_frame {
+            // The rest of the frame is spent waiting for the display
+            if self.vblank_wait {
+                break;
             }
-            0x9000 => opcode_9_xy0(self, self.cur_opcode),
-            0xA000 => {
-                opcode_a_nnn(self, self.cur_opcode);
-                self.program_counter += 2;
-            }
-            0xB000 => {
-                opcode_b_nnn(self, self.cur_opcode);
-                self.program_counter += 2;
-            }
-            0xC000 => {
-                opcode_c_xnn(self, self.cur_opcode);
-                self.program_counter += 2;
-            }
-           0xD000 => {
-            opcode_d_xyn(self, self.cur_opcode);
-            self.program_counter += 2;
 
-            }
-            0xE000 => match self.cur_opcode & 0x00FF {
-                0x009E => {opcode_e_x9e(self, self.cur_opcode)},
-                0x00A1 => {opcode_e_xa1(self, self.cur_opcode)},
-                _ => {}
-            }
-            0xF000 => match self.cur_opcode & 0x00FF {
-                0x0007 => {
-                    opcode_f_x07(self, self.cur_opcode);
-                    self.program_counter += 2;
-                },
-                0x000A => {
-                    opcode_f_x0a(self, self.cur_opcode);
-                },
-                0x0015 => {
-                    opcode_f_x15(self, self.cur_opcode);
-                    self.program_counter += 2;
-                },
-                0x0018 => {
-                    opcode_f_x18(self, self.cur_opcode);
-                    self.program_counter += 2;
-                },
-                0x001E => {
-                    opcode_f_x1e(self, self.cur_opcode);
-                    self.program_counter += 2;
-                },
-                0x0029 => {
-                    opcode_f_x29(self, self.cur_opcode);
-                    self.program_counter += 2;
-                },
-                0x0033 => {
-                    opcode_f_x33(self, self.cur_opcode);
-                    self.program_counter += 2;
-                },
-                0x0055 => {
-                    opcode_f_x55(self, self.cur_opcode);
-                    self.program_counter += 2;
-                },
-                0x0065 => {
-                    opcode_f_x65(self, self.cur_opcode);
-                    self.program_counter += 2;
-                },
-                _ => {}
-            }
-            _ => {}
+            self.update()?;
         }
 
-       /* println!("\rProgram Info: \nPC: {:?} \nOPCODE: 0x{:X} \nI: 0x{:X} \nSP: 0x{:X} \nREG: {:?} \nSTACK: {:?}",
-        self.program_counter, self.cur_opcode, self.index_register, self.stack_pointer,
-       self.registers, self.stack); */
-
+        self.tick_timers();
+        // Save states can start the count anywhere, up to u64::MAX
+        self.frame_count = self.frame_count.wrapping_add(1);
+        Ok(())
+    }
 
+    pub fn tick_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
         if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                println!("BEEP!");
-            }
-            
             self.sound_timer -= 1;
         }
+    }
+
+    // The buzzer sounds for as long as the sound timer is non-zero.
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // What the buzzer plays, None for the plain tone of CHIP-8.
+    pub fn sound_pattern(&self) -> Option<Pattern> {
+        self.audio_pattern.map(|bits| Pattern {
+            bits,
+            pitch: self.pitch,
+        })
+    }
 
+    // Runs the instruction at the program counter. Programs that overflow the
+    // stack, reach past the end of memory or run an unknown opcode get an
+    // error naming the instruction and leave the machine as it was before it.
+    pub fn update(&mut self) -> Result<(), String> {
+        let pc = self.program_counter;
+
+        self.execute().map_err(|e| {
+            let opcode = self.cur_opcode;
+            format!("{:03X}: {:04X} {}: {}", pc, opcode, Instruction::decode(opcode), e)
+        })
     }
+
+    fn execute(&mut self) -> Result<(), String> {
+        self.get_next_opcode()?;
+
+        let opcode = self.cur_opcode;
+
+        // Handlers that leave the program counter alone are followed by the
+        // usual step to the next instruction, jumps and skips move it
+        // themselves.
+        match Instruction::decode(opcode) {
+            Instruction::Sys(_) => self.program_counter += 2,
+            Instruction::Cls => {
+                self.framebuffer = [Default::default(); SCREEN_WIDTH * SCREEN_HEIGHT];
+                self.dirty.mark_all(SCREEN_WIDTH, SCREEN_HEIGHT);
+                self.program_counter += 2
+            }
+            Instruction::Ret => opcode_0_0ee(self)?,
+            Instruction::Jump(_) => opcode_1_nnn(self, opcode),
+            Instruction::Call(_) => opcode_2_nnn(self, opcode)?,
+            Instruction::SkipEqImm(..) => opcode_3_xnn(self, opcode),
+            Instruction::SkipNeImm(..) => opcode_4_xnn(self, opcode),
+            Instruction::SkipEqReg(..) => opcode_5_xy0(self, opcode),
+            Instruction::SkipNeReg(..) => opcode_9_xy0(self, opcode),
+            Instruction::JumpV0(_) => opcode_b_nnn(self, opcode),
+            Instruction::SkipKey(_) => opcode_e_x9e(self, opcode),
+            Instruction::SkipNoKey(_) => opcode_e_xa1(self, opcode),
+            Instruction::WaitKey(_) => opcode_f_x0a(self, opcode),
+            Instruction::Unknown(_) => return Err("unknown opcode".to_owned()),
+            instruction => {
+                match instruction {
+                    Instruction::LoadImm(..) => opcode_6_xnn(self, opcode),
+                    Instruction::AddImm(..) => opcode_7_xnn(self, opcode),
+                    Instruction::Move(..) => opcode_8_xy0(self, opcode),
+                    Instruction::Or(..) => opcode_8_xy1(self, opcode),
+                    Instruction::And(..) => opcode_8_xy2(self, opcode),
+                    Instruction::Xor(..) => opcode_8_xy3(self, opcode),
+                    Instruction::AddReg(..) => opcode_8_xy4(self, opcode),
+                    Instruction::Sub(..) => opcode_8_xy5(self, opcode),
+                    Instruction::ShiftRight(..) => opcode_8_xy6(self, opcode),
+                    Instruction::SubN(..) => opcode_8_xy7(self, opcode),
+                    Instruction::ShiftLeft(..) => opcode_8_xye(self, opcode),
+                    Instruction::LoadIndex(_) => opcode_a_nnn(self, opcode),
+                    Instruction::Random(..) => opcode_c_xnn(self, opcode),
+                    Instruction::Draw(..) => opcode_d_xyn(self, opcode)?,
+                    Instruction::LoadDelay(_) => opcode_f_x07(self, opcode),
+                    Instruction::SetDelay(_) => opcode_f_x15(self, opcode),
+                    Instruction::SetSound(_) => opcode_f_x18(self, opcode),
+                    Instruction::AddIndex(_) => opcode_f_x1e(self, opcode),
+                    Instruction::LoadFont(_) => opcode_f_x29(self, opcode),
+                    Instruction::StoreBcd(_) => opcode_f_x33(self, opcode)?,
+                    Instruction::Store(_) => opcode_f_x55(self, opcode)?,
+                    Instruction::Load(_) => opcode_f_x65(self, opcode)?,
+                    Instruction::LoadAudio => opcode_f_002(self)?,
+                    Instruction::SetPitch(_) => opcode_f_x3a(self, opcode),
+                    _ => unreachable!(),
+                }
+
+                self.program_counter += 2;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn rom_hash(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
 }
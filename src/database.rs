@@ -0,0 +1,205 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use serde::Deserialize;
+
+use crate::{cpu::MAX_INSTRUCTIONS_PER_FRAME, gpu::Palette, quirks::Quirks};
+
+// Recommended settings for known ROMs, looked up by SHA-1. The files under
+// data/database use the format of the community chip-8-database
+// (https://github.com/chip-8/chip-8-database), so entries can be copied over
+// from there as they are:
+//
+//   sha1-hashes.json  hash -> index into programs.json
+//   programs.json     titles, authors and the settings of each ROM
+//   platforms.json    the quirks and clock of every interpreter
+//
+// Fields this emulator has no use for are ignored.
+
+const PROGRAMS: &str = include_str!("../data/database/programs.json");
+const HASHES: &str = include_str!("../data/database/sha1-hashes.json");
+const PLATFORMS: &str = include_str!("../data/database/platforms.json");
+
+// Keypad layout names of the database and the host keys they stand for
+const ACTION_KEYS: [(&str, [&str; 2]); 6] = [
+    ("up", ["Up", "Pad dpup"]),
+    ("down", ["Down", "Pad dpdown"]),
+    ("left", ["Left", "Pad dpleft"]),
+    ("right", ["Right", "Pad dpright"]),
+    ("a", ["Space", "Pad a"]),
+    ("b", ["Return", "Pad b"]),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    // Name of the interpreter the ROM was written for
+    pub platform: String,
+    pub quirks: Quirks,
+    pub instructions_per_frame: usize,
+    pub palette: Option<Palette>,
+    // Host key and the CHIP-8 key it should press
+    pub keys: Vec<(&'static str, u8)>,
+}
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    release: Option<String>,
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    // In order of preference
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, PlatformQuirks>,
+    tickrate: Option<usize>,
+    colors: Option<Colors>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    // Unlit first, then lit
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    name: String,
+    default_tickrate: usize,
+    quirks: PlatformQuirks,
+}
+
+// Quirks the database leaves out keep the value of the default profile.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct PlatformQuirks {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl PlatformQuirks {
+    fn apply(&self, quirks: &mut Quirks) {
+        let fields = [
+            (self.shift, &mut quirks.shift),
+            (self.memory_increment_by_x, &mut quirks.memory_increment_by_x),
+            (self.memory_leave_i_unchanged, &mut quirks.memory_leave_i_unchanged),
+            (self.wrap, &mut quirks.wrap),
+            (self.jump, &mut quirks.jump),
+            (self.vblank, &mut quirks.vblank),
+            (self.logic, &mut quirks.logic),
+        ];
+
+        for (value, quirk) in fields {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+    }
+}
+
+// The three files parsed into one table, built on the first lookup
+struct Database {
+    // Lowercase SHA-1 -> index into programs and the ROM's own settings
+    roms: HashMap<String, (usize, Rom)>,
+    programs: Vec<Program>,
+    platforms: Vec<Platform>,
+}
+
+fn database() -> &'static Database {
+    static DATABASE: OnceLock<Database> = OnceLock::new();
+
+    DATABASE.get_or_init(|| {
+        // The files are part of the binary, so they are known to parse
+        let hashes: HashMap<String, usize> =
+            serde_json::from_str(HASHES).expect("sha1-hashes.json is valid");
+        let mut programs: Vec<Program> =
+            serde_json::from_str(PROGRAMS).expect("programs.json is valid");
+        let platforms: Vec<Platform> =
+            serde_json::from_str(PLATFORMS).expect("platforms.json is valid");
+
+        // The ROMs move out of their programs, which keep the rest
+        let mut roms = HashMap::new();
+        for (hash, index) in hashes {
+            let Some(program) = programs.get_mut(index) else {
+                continue;
+            };
+            let found = program.roms.keys().find(|key| key.eq_ignore_ascii_case(&hash)).cloned();
+
+            if let Some(rom) = found.and_then(|key| program.roms.remove(&key)) {
+                roms.insert(hash.to_lowercase(), (index, rom));
+            }
+        }
+
+        Database {
+            roms,
+            programs,
+            platforms,
+        }
+    })
+}
+
+// The entry for a ROM, None when it is not in the database.
+pub fn lookup(rom_hash: &str) -> Option<RomInfo> {
+    let database = database();
+    let (index, rom) = database.roms.get(&rom_hash.to_lowercase())?;
+    let program = &database.programs[*index];
+
+    // The first platform this emulator knows about
+    let platform = rom
+        .platforms
+        .iter()
+        .find_map(|id| database.platforms.iter().find(|platform| &platform.id == id))?;
+
+    let mut quirks = Quirks::default();
+    platform.quirks.apply(&mut quirks);
+
+    if let Some(overrides) = rom.quirky_platforms.get(&platform.id) {
+        overrides.apply(&mut quirks);
+    }
+
+    let instructions_per_frame = rom
+        .tickrate
+        .unwrap_or(platform.default_tickrate)
+        .clamp(1, MAX_INSTRUCTIONS_PER_FRAME);
+
+    // Only plain two colour palettes, the extra XO-CHIP planes are not drawn
+    let palette = rom.colors.as_ref().and_then(|colors| match colors.pixels.as_slice() {
+        [off, on, ..] => format!("{},{}", on, off).parse().ok(),
+        _ => None,
+    });
+
+    let keys = ACTION_KEYS
+        .iter()
+        .filter_map(|(action, host_keys)| Some((host_keys, *rom.keys.get(*action)?)))
+        .flat_map(|(host_keys, key)| host_keys.iter().map(move |host_key| (*host_key, key)))
+        .filter(|&(_, key)| key <= 0xF)
+        .collect();
+
+    Some(RomInfo {
+        title: program.title.clone(),
+        authors: program.authors.clone(),
+        release: program.release.clone(),
+        platform: platform.name.clone(),
+        quirks,
+        instructions_per_frame,
+        palette,
+        keys,
+    })
+}
@@ -0,0 +1,87 @@
+// Tracks which parts of the framebuffer changed since the last presented
+// frame, so renderers can skip redrawing what did not change.
+//
+// Changed rows are kept as a bit set (displays up to 64 rows) together with
+// the bounding rectangle of every changed pixel.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DirtyRegion {
+    rows: u64,
+    bounds: Option<Rect>,
+}
+
+impl DirtyRegion {
+    pub fn mark_pixel(&mut self, x: usize, y: usize) {
+        self.mark_rect(Rect {
+            x,
+            y,
+            width: 1,
+            height: 1,
+        });
+    }
+
+    pub fn mark_rect(&mut self, rect: Rect) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+
+        for y in rect.y..(rect.y + rect.height).min(64) {
+            self.rows |= 1 << y;
+        }
+
+        self.extend_bounds(rect);
+    }
+
+    fn extend_bounds(&mut self, rect: Rect) {
+        self.bounds = Some(match self.bounds {
+            Some(bounds) => {
+                let x = bounds.x.min(rect.x);
+                let y = bounds.y.min(rect.y);
+                let right = (bounds.x + bounds.width).max(rect.x + rect.width);
+                let bottom = (bounds.y + bounds.height).max(rect.y + rect.height);
+
+                Rect {
+                    x,
+                    y,
+                    width: right - x,
+                    height: bottom - y,
+                }
+            }
+            None => rect,
+        });
+    }
+
+    // For instructions that touch the whole screen (00E0, scrolling...)
+    pub fn mark_all(&mut self, width: usize, height: usize) {
+        self.mark_rect(Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_none()
+    }
+
+    pub fn is_row_dirty(&self, y: usize) -> bool {
+        y < 64 && self.rows & (1 << y) != 0
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..64).filter(|&y| self.is_row_dirty(y))
+    }
+
+    pub fn bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+}
@@ -0,0 +1,33 @@
+use std::fmt::Write;
+
+use crate::{asm::ORIGIN, instruction::Instruction};
+
+// Lists a ROM one instruction per line, with the address and the raw opcode
+// in a comment. The output is valid input for the assembler.
+//
+// CHIP-8 programs mix code and data freely, so every aligned pair of bytes is
+// shown as an instruction, sprites included. Words that are no instruction
+// at all become DW.
+pub fn disassemble(rom: &[u8]) -> String {
+    let mut out = String::new();
+
+    for (index, chunk) in rom.chunks(2).enumerate() {
+        let address = ORIGIN as usize + index * 2;
+
+        let _ = match *chunk {
+            [high, low] => {
+                let opcode = u16::from_be_bytes([high, low]);
+                let instruction = Instruction::decode(opcode).to_string();
+                writeln!(out, "    {:<20} ; {:03X}: {:04X}", instruction, address, opcode)
+            }
+            // An odd sized ROM leaves one byte at the end
+            [byte] => {
+                let data = format!("DB 0x{:02X}", byte);
+                writeln!(out, "    {:<20} ; {:03X}: {:02X}", data, address, byte)
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    out
+}
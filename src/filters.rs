@@ -0,0 +1,294 @@
+use std::str::FromStr;
+
+use crate::gpu::Palette;
+
+// Software post-processing for the SDL2 window and image captures.
+//
+// Everything here works on a plain RGB pixel buffer on the CPU, so the retro
+// look does not need any GPU support. The framebuffer is first upscaled into
+// cells of `scale` x `scale` pixels, then every filter of the chain runs in
+// order on the result before it is copied into the texture.
+
+pub const PIXEL_ON: u32 = 0xFFFFFF;
+pub const PIXEL_OFF: u32 = 0x000000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelBuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u32>, // 0x00RRGGBB
+}
+
+impl PixelBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    // Copies the pixels into a RGB888 byte buffer (what a SDL2 RGB24
+    // texture expects).
+    pub fn to_rgb24(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 3);
+
+        for pixel in &self.pixels {
+            bytes.push((pixel >> 16) as u8);
+            bytes.push((pixel >> 8) as u8);
+            bytes.push(*pixel as u8);
+        }
+
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    // Darkens the last row of every cell, like the gaps between CRT lines.
+    Scanlines(f32),
+    // Darkens the last row and column of every cell, like an LCD grid.
+    Grid(f32),
+    // Adds a blurred copy of the image on top of itself so lit pixels glow.
+    Bloom(f32),
+    // Barrel distortion, bends the image like a curved CRT tube.
+    Curvature(f32),
+}
+
+impl Filter {
+    pub fn apply(&self, buffer: &mut PixelBuffer, scale: usize) {
+        match *self {
+            Filter::Scanlines(strength) => {
+                if scale < 2 {
+                    return;
+                }
+
+                for y in (scale - 1..buffer.height).step_by(scale) {
+                    let row = &mut buffer.pixels[y * buffer.width..(y + 1) * buffer.width];
+
+                    for pixel in row {
+                        *pixel = darken(*pixel, strength);
+                    }
+                }
+            }
+            Filter::Grid(strength) => {
+                if scale < 2 {
+                    return;
+                }
+
+                for y in 0..buffer.height {
+                    for x in 0..buffer.width {
+                        if x % scale == scale - 1 || y % scale == scale - 1 {
+                            let idx = y * buffer.width + x;
+                            buffer.pixels[idx] = darken(buffer.pixels[idx], strength);
+                        }
+                    }
+                }
+            }
+            Filter::Bloom(strength) => {
+                let radius = (scale / 2).max(1);
+                let blurred = box_blur(buffer, radius);
+
+                for (pixel, glow) in buffer.pixels.iter_mut().zip(blurred.pixels) {
+                    *pixel = add(*pixel, darken(glow, 1.0 - strength));
+                }
+            }
+            Filter::Curvature(amount) => {
+                let source = buffer.clone();
+                let half_w = buffer.width as f32 / 2.0;
+                let half_h = buffer.height as f32 / 2.0;
+
+                for y in 0..buffer.height {
+                    for x in 0..buffer.width {
+                        // Position relative to the centre, from -1.0 to 1.0
+                        let u = (x as f32 + 0.5 - half_w) / half_w;
+                        let v = (y as f32 + 0.5 - half_h) / half_h;
+                        let bend = 1.0 + amount * (u * u + v * v);
+
+                        let src_x = (u * bend * half_w + half_w).floor();
+                        let src_y = (v * bend * half_h + half_h).floor();
+
+                        let inside = src_x >= 0.0
+                            && src_y >= 0.0
+                            && (src_x as usize) < source.width
+                            && (src_y as usize) < source.height;
+
+                        buffer.pixels[y * buffer.width + x] = if inside {
+                            source.pixels[src_y as usize * source.width + src_x as usize]
+                        } else {
+                            PIXEL_OFF
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    // Parses a single filter, like "scanlines" or "scanlines=0.4".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (s.trim(), None),
+        };
+
+        let value = match value {
+            Some(value) => value
+                .parse::<f32>()
+                .map_err(|_| format!("invalid value '{}' for filter '{}'", value, name))?,
+            None => match name {
+                "curvature" => 0.1,
+                _ => 0.5,
+            },
+        };
+
+        match name {
+            "scanlines" => Ok(Filter::Scanlines(value.clamp(0.0, 1.0))),
+            "grid" => Ok(Filter::Grid(value.clamp(0.0, 1.0))),
+            "bloom" => Ok(Filter::Bloom(value.clamp(0.0, 1.0))),
+            "curvature" => Ok(Filter::Curvature(value.clamp(0.0, 1.0))),
+            _ => Err(format!("unknown filter '{}'", name)),
+        }
+    }
+}
+
+// Largest scale the command line and the settings file accept
+pub const MAX_SCALE: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterChain {
+    pub scale: usize,
+    pub filters: Vec<Filter>,
+}
+
+impl FilterChain {
+    pub fn new(scale: usize) -> Self {
+        Self {
+            scale: scale.max(1),
+            filters: Vec::new(),
+        }
+    }
+
+    // Upscales a brightness buffer (one byte per CHIP-8 pixel, as produced by
+    // Phosphor::update) into palette colours and runs every filter on it.
+    pub fn process(
+        &self,
+        brightness: &[u8],
+        width: usize,
+        height: usize,
+        palette: Palette,
+    ) -> PixelBuffer {
+        let mut buffer = PixelBuffer::new(width * self.scale, height * self.scale);
+
+        for y in 0..buffer.height {
+            for x in 0..buffer.width {
+                let level = brightness[(y / self.scale) * width + x / self.scale];
+                buffer.pixels[y * buffer.width + x] = blend(palette.off, palette.on, level);
+            }
+        }
+
+        for filter in &self.filters {
+            filter.apply(&mut buffer, self.scale);
+        }
+
+        buffer
+    }
+}
+
+impl FromStr for FilterChain {
+    type Err = String;
+
+    // Parses a comma separated chain, like "scale=8,scanlines=0.4,bloom".
+    // The scale entry is optional and defaults to 8.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chain = FilterChain::new(8);
+
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            if let Some(scale) = entry.strip_prefix("scale=") {
+                chain.scale = scale
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid scale '{}'", scale))?;
+
+                if !(1..=MAX_SCALE).contains(&chain.scale) {
+                    return Err(format!("scale must be between 1 and {}", MAX_SCALE));
+                }
+            } else {
+                chain.filters.push(entry.parse()?);
+            }
+        }
+
+        Ok(chain)
+    }
+}
+
+fn channels(color: u32) -> [u32; 3] {
+    [(color >> 16) & 0xFF, (color >> 8) & 0xFF, color & 0xFF]
+}
+
+fn from_channels(channels: [u32; 3]) -> u32 {
+    (channels[0].min(0xFF) << 16) | (channels[1].min(0xFF) << 8) | channels[2].min(0xFF)
+}
+
+fn darken(color: u32, strength: f32) -> u32 {
+    let keep = 1.0 - strength.clamp(0.0, 1.0);
+    from_channels(channels(color).map(|c| (c as f32 * keep) as u32))
+}
+
+fn add(a: u32, b: u32) -> u32 {
+    let (a, b) = (channels(a), channels(b));
+    from_channels([a[0] + b[0], a[1] + b[1], a[2] + b[2]])
+}
+
+fn blend(off: u32, on: u32, level: u8) -> u32 {
+    let (off, on) = (channels(off), channels(on));
+    let level = level as u32;
+    let mix = |i: usize| (on[i] * level + off[i] * (255 - level)) / 255;
+
+    from_channels([mix(0), mix(1), mix(2)])
+}
+
+// Separable box blur, first along the rows and then along the columns.
+fn box_blur(buffer: &PixelBuffer, radius: usize) -> PixelBuffer {
+    let (width, height) = (buffer.width, buffer.height);
+    let mut horizontal = PixelBuffer::new(width, height);
+    let mut output = PixelBuffer::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let start = x.saturating_sub(radius);
+            let end = (x + radius).min(width - 1);
+            let pixels = (start..=end).map(|sx| buffer.pixels[y * width + sx]);
+            horizontal.pixels[y * width + x] = average(pixels);
+        }
+    }
+
+    for y in 0..height {
+        for x in 0..width {
+            let start = y.saturating_sub(radius);
+            let end = (y + radius).min(height - 1);
+            let pixels = (start..=end).map(|sy| horizontal.pixels[sy * width + x]);
+            output.pixels[y * width + x] = average(pixels);
+        }
+    }
+
+    output
+}
+
+fn average(pixels: impl Iterator<Item = u32>) -> u32 {
+    let mut sum = [0u32; 3];
+    let mut count = 0;
+
+    for pixel in pixels {
+        let c = channels(pixel);
+        sum[0] += c[0];
+        sum[1] += c[1];
+        sum[2] += c[2];
+        count += 1;
+    }
+
+    from_channels(sum.map(|c| c / count.max(1)))
+}
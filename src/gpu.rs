@@ -1,78 +1,180 @@
-/*extern crate rand;
-use rand::Rng;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
-use std::collections::VecDeque;
-use std::sync::{Arc, Barrier, Mutex};
-use std::thread;
-use std::time::{Duration, Instant};
-
-use crate::cpu::CPU;
-
-type Framebuffer = [u8; SCREEN_WIDTH * SCREEN_HEIGHT];
-
-const SCREEN_WIDTH: usize = 64;
-const SCREEN_HEIGHT: usize = 32;
-
-pub(crate) struct GPUCommand {
-    id: u32,
-    x: u8,
-    y: u8,
-    height: u8,
+use std::str::FromStr;
+
+use crate::{
+    dirty::DirtyRegion,
+    filters::{PIXEL_OFF, PIXEL_ON},
+};
+
+mod capture;
+#[cfg(feature = "sdl")]
+mod gamepad;
+mod null;
+#[cfg(feature = "sdl")]
+mod sdl;
+mod terminal;
+
+pub use capture::CaptureRenderer;
+pub use null::NullRenderer;
+#[cfg(feature = "sdl")]
+pub use sdl::SdlRenderer;
+pub use terminal::{TerminalRenderer, MAX_KEY_HOLD_MS};
+
+// Everything a renderer needs to draw one frame. The core only decides when a
+// frame is ready, how it ends up on screen is up to the Renderer.
+pub struct FrameView<'a> {
+    pub width: usize,
+    pub height: usize,
+    // Per pixel brightness after the phosphor filter, 0 (off) to 255 (lit)
+    pub brightness: &'a [u8],
+    // What changed since the previous frame. Renderers that keep their output
+    // around can redraw just these parts.
+    pub dirty: &'a DirtyRegion,
 }
 
-pub(crate) struct GPU {
-    pub framebuffer: Arc<Mutex<Framebuffer>>,
-    command_buffers: Arc<Mutex<VecDeque<GPUCommand>>>,
-    barrier: Option<Arc<Barrier>>,
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub on: u32, // 0x00RRGGBB
+    pub off: u32,
 }
 
-impl GPU {
-    pub fn new() -> Self {
+impl Default for Palette {
+    fn default() -> Self {
         Self {
-            framebuffer: Arc::new(Mutex::new([Default::default(); SCREEN_WIDTH * SCREEN_HEIGHT])),
-            command_buffers: Arc::new(Mutex::new(VecDeque::new())),
-            barrier: None
+            on: PIXEL_ON,
+            off: PIXEL_OFF,
         }
     }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    // A preset (white, amber, green or lcd) or two RRGGBB hex colours for
+    // lit and unlit pixels, like "ffb000,1a1000".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (on, off) = match s {
+            "white" => return Ok(Palette::default()),
+            "amber" => (0xFFB000, 0x1A1000),
+            "green" => (0x33FF66, 0x001A08),
+            "lcd" => (0x0F380F, 0x9BBC0F),
+            _ => {
+                let (on, off) = s
+                    .split_once(',')
+                    .ok_or_else(|| format!("unknown palette '{}'", s))?;
+                (color(on)?, color(off)?)
+            }
+        };
+
+        Ok(Palette { on, off })
+    }
+}
 
-    pub fn initialize(&mut self) -> Arc<Barrier> {
-        let barrier = Arc::new(Barrier::new(1));
+fn color(hex: &str) -> Result<u32, String> {
+    let hex = hex.trim();
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
 
-        self.barrier = Some(Arc::clone(&barrier));
+    match u32::from_str_radix(digits, 16) {
+        Ok(color) if digits.len() == 6 => Ok(color),
+        _ => Err(format!("invalid colour '{}', expected RRGGBB", hex)),
+    }
+}
+
+// How the SDL2 window fits the frame, F10 cycles through them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleMode {
+    // Largest whole multiple of the CHIP-8 resolution that fits, so every
+    // CHIP-8 pixel covers the same number of host pixels.
+    Integer,
+    // Fills the whole window, ignoring the aspect ratio.
+    Stretch,
+    // Largest size with the original aspect ratio, letterboxed.
+    Aspect,
+}
 
-        return barrier;
+impl ScaleMode {
+    pub fn next(self) -> Self {
+        match self {
+            ScaleMode::Integer => ScaleMode::Aspect,
+            ScaleMode::Aspect => ScaleMode::Stretch,
+            ScaleMode::Stretch => ScaleMode::Integer,
+        }
     }
+}
 
-    pub fn execute(&mut self,cpu:Arc<Mutex<CPU>>) {
-        let commands: Vec<GPUCommand> = {
-            let mut command_buffer = self.command_buffers.lock().unwrap();
-            command_buffer.drain(..).collect() // Collecting maintains FIFO order
-        };
-        let mut framebuffer = self.framebuffer.lock().unwrap();
+impl FromStr for ScaleMode {
+    type Err = String;
 
-        commands.into_par_iter().for_each(|command | {
-            let mut pixel:u8;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "integer" => Ok(ScaleMode::Integer),
+            "stretch" => Ok(ScaleMode::Stretch),
+            "aspect" => Ok(ScaleMode::Aspect),
+            _ => Err(format!("unknown scale mode '{}'", s)),
+        }
+    }
+}
+
+// Emulator controls bound to host keys. Frontends report these keys like any
+// other, the main loop picks them out before the keymap sees them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    NextAudio,   // F2, the next sound output
+    SlowerClock, // F3, fewer instructions per frame
+    FasterClock, // F4
+    TogglePause, // F5
+    Step,        // F6, one frame while paused
+    Slower,      // F7
+    Faster,      // F8
+    ToggleMute,  // F9
+    Rewind,      // Backspace, held
+}
+
+impl Hotkey {
+    pub fn from_key(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "f2" => Some(Hotkey::NextAudio),
+            "f3" => Some(Hotkey::SlowerClock),
+            "f4" => Some(Hotkey::FasterClock),
+            "f5" => Some(Hotkey::TogglePause),
+            "f6" => Some(Hotkey::Step),
+            "f7" => Some(Hotkey::Slower),
+            "f8" => Some(Hotkey::Faster),
+            "f9" => Some(Hotkey::ToggleMute),
+            "backspace" => Some(Hotkey::Rewind),
+            _ => None,
+        }
+    }
+}
 
-            cpu.lock().unwrap().registers[0xF] = 0;
-            for yline in 0..command.height {
-                pixel = cpu.lock().unwrap().game_memory[(cpu.lock().unwrap().index_register + yline as u16) as usize];
+// Things happening on the host side that the emulator loop has to react to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostEvent {
+    Quit,
+    // Host keys by name, see Keymap
+    KeyDown(String),
+    KeyUp(String),
+}
 
-                for xline in 0..8 {
-                    if (pixel & (0x80 >> xline)) != 0 {
-                        if self.framebuffer.lock().unwrap()[(command.x + xline + ((command.y + yline) * 64)) as usize] == 1 {
-                            cpu.lock().unwrap().registers[0xF] = 1;
-                        }
+pub trait Renderer {
+    fn present(&mut self, frame: &FrameView) -> Result<(), String>;
 
-                        self.framebuffer.lock().unwrap()[(command.x + xline + ((command.y + yline) * 64)) as usize] ^= 1;
-                    }
-                }
-            }
-        });
+    // Called when the CHIP-8 display resolution changes.
+    fn resize(&mut self, width: usize, height: usize) -> Result<(), String>;
 
-        self.barrier.as_ref().unwrap().wait();   
+    fn set_palette(&mut self, palette: Palette);
+
+    // A short line about the emulator settings (clock, speed...). Frontends
+    // show it wherever it fits, or not at all.
+    fn set_status(&mut self, _status: &str) {}
+
+    // Frontends with a window or a terminal also own the host input.
+    fn poll_events(&mut self) -> Vec<HostEvent> {
+        Vec::new()
     }
 
-    pub fn push_command(&self,command: GPUCommand) {
-        self.command_buffers.lock().unwrap().push_back(command);
+    // Whether the host key `name` reaches the emulator as some other key, so
+    // a Keymap binding it would never see it.
+    fn misreads_key(&self, _name: &str) -> bool {
+        false
     }
-}*/
\ No newline at end of file
+}
@@ -0,0 +1,67 @@
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use super::{FrameView, Palette, Renderer};
+use crate::filters::FilterChain;
+
+// Writes every presented frame as a numbered PNG into a directory, for
+// screenshots and frame-by-frame recordings.
+pub struct CaptureRenderer {
+    directory: PathBuf,
+    filters: FilterChain,
+    palette: Palette,
+    frame: u64,
+}
+
+impl CaptureRenderer {
+    pub fn new(directory: PathBuf, filters: FilterChain) -> Result<Self, String> {
+        fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            directory,
+            filters,
+            palette: Palette::default(),
+            frame: 0,
+        })
+    }
+}
+
+impl Renderer for CaptureRenderer {
+    fn present(&mut self, frame: &FrameView) -> Result<(), String> {
+        let image = self
+            .filters
+            .process(frame.brightness, frame.width, frame.height, self.palette);
+
+        let path = self.directory.join(format!("frame_{:06}.png", self.frame));
+        let file = File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(file),
+            image.width as u32,
+            image.height as u32,
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&image.to_rgb24()))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    fn resize(&mut self, _width: usize, _height: usize) -> Result<(), String> {
+        // Every frame is encoded at its own size
+        Ok(())
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+}
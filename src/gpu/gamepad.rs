@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use sdl2::{
+    controller::{Axis, Button, GameController},
+    event::Event,
+    GameControllerSubsystem,
+};
+
+use super::HostEvent;
+
+// How far a stick has to be pushed before it counts as a direction.
+const STICK_DEADZONE: i16 = 16_000;
+
+// Turns SDL2 game controller events into host key events. Controller inputs
+// are named "Pad " followed by the SDL2 button name ("Pad a", "Pad dpup"...),
+// so they are bound to the hex keypad by the same Keymap as the keyboard.
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    // Open controllers by joystick instance id
+    controllers: HashMap<u32, GameController>,
+    // Let the left stick press the D-pad buttons
+    stick_as_dpad: bool,
+    // Buttons currently down, per controller
+    buttons_held: HashMap<u32, Vec<Button>>,
+    // D-pad directions currently pushed by a stick, per controller
+    stick_held: HashMap<u32, Vec<Button>>,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem, stick_as_dpad: bool) -> Self {
+        Self {
+            subsystem,
+            controllers: HashMap::new(),
+            buttons_held: HashMap::new(),
+            stick_held: HashMap::new(),
+            stick_as_dpad,
+        }
+    }
+
+    // Handles controller events, returns false for anything else. Controllers
+    // present at start up also arrive as ControllerDeviceAdded.
+    pub fn handle(&mut self, event: &Event, events: &mut Vec<HostEvent>) -> bool {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        info!("Game controller connected: {}", controller.name());
+                        self.controllers.insert(controller.instance_id(), controller);
+                    }
+                    Err(e) => warn!("Failed to open game controller {}: {}", which, e),
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.remove(&which);
+
+                // Nothing will release what was held on an unplugged pad
+                let mut held = self.buttons_held.remove(&which).unwrap_or_default();
+                for button in self.stick_held.remove(&which).unwrap_or_default() {
+                    if !held.contains(&button) {
+                        held.push(button);
+                    }
+                }
+
+                for button in held {
+                    events.push(HostEvent::KeyUp(button_name(button)));
+                }
+            }
+            // The stick and the D-pad press the same keys, a direction is
+            // only released once neither of them holds it
+            Event::ControllerButtonDown { which, button, .. } => {
+                let held = self.buttons_held.entry(which).or_default();
+                if !held.contains(&button) {
+                    held.push(button);
+                }
+
+                if !holds(&self.stick_held, which, button) {
+                    events.push(HostEvent::KeyDown(button_name(button)));
+                }
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(held) = self.buttons_held.get_mut(&which) {
+                    held.retain(|&b| b != button);
+                }
+
+                if !holds(&self.stick_held, which, button) {
+                    events.push(HostEvent::KeyUp(button_name(button)));
+                }
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } if self.stick_as_dpad => {
+                let (negative, positive) = match axis {
+                    Axis::LeftX => (Button::DPadLeft, Button::DPadRight),
+                    Axis::LeftY => (Button::DPadUp, Button::DPadDown),
+                    _ => return true,
+                };
+
+                let held = self.stick_held.entry(which).or_default();
+
+                for (button, pushed) in [
+                    (negative, value < -STICK_DEADZONE),
+                    (positive, value > STICK_DEADZONE),
+                ] {
+                    let was_pushed = held.contains(&button);
+                    let on_dpad = holds(&self.buttons_held, which, button);
+
+                    if pushed && !was_pushed {
+                        held.push(button);
+                        if !on_dpad {
+                            events.push(HostEvent::KeyDown(button_name(button)));
+                        }
+                    } else if !pushed && was_pushed {
+                        held.retain(|&b| b != button);
+                        if !on_dpad {
+                            events.push(HostEvent::KeyUp(button_name(button)));
+                        }
+                    }
+                }
+            }
+            Event::ControllerAxisMotion { .. } => {}
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+fn holds(held: &HashMap<u32, Vec<Button>>, which: u32, button: Button) -> bool {
+    held.get(&which).is_some_and(|buttons| buttons.contains(&button))
+}
+
+fn button_name(button: Button) -> String {
+    format!("Pad {}", button.string())
+}
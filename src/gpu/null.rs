@@ -0,0 +1,20 @@
+use super::{FrameView, Palette, Renderer};
+
+// Headless renderer, drops every frame. Used when only the emulation matters.
+#[derive(Debug, Default)]
+pub struct NullRenderer {
+    pub frames: u64,
+}
+
+impl Renderer for NullRenderer {
+    fn present(&mut self, _frame: &FrameView) -> Result<(), String> {
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn resize(&mut self, _width: usize, _height: usize) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn set_palette(&mut self, _palette: Palette) {}
+}
@@ -0,0 +1,267 @@
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{Canvas, Texture, TextureCreator},
+    video::{FullscreenType, Window, WindowContext},
+    EventPump,
+};
+
+use super::{gamepad::Gamepads, FrameView, HostEvent, Palette, Renderer, ScaleMode};
+use crate::filters::FilterChain;
+
+// SDL2 window renderer. The frame goes through the software filter chain
+// first and the resulting pixels are streamed into a texture.
+//
+// F10 cycles through the scale modes and F11 toggles fullscreen. Game
+// controllers are picked up here too, since they share the event pump.
+pub struct SdlRenderer {
+    title: String,
+    canvas: Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
+    texture: Option<(Texture, usize, usize)>,
+    event_pump: EventPump,
+    gamepads: Gamepads,
+    filters: FilterChain,
+    // Filter scale asked for by the user, integer mode overrides it
+    base_scale: usize,
+    scale_mode: ScaleMode,
+    palette: Palette,
+    redraw: bool,
+}
+
+impl SdlRenderer {
+    pub fn new(
+        title: &str,
+        width: usize,
+        height: usize,
+        filters: FilterChain,
+        scale_mode: ScaleMode,
+        stick_as_dpad: bool,
+    ) -> Result<Self, String> {
+        let sdl = sdl2::init()?;
+        let video = sdl.video()?;
+        let gamepads = Gamepads::new(sdl.game_controller()?, stick_as_dpad);
+
+        let window = video
+            .window(
+                title,
+                (width * filters.scale) as u32,
+                (height * filters.scale) as u32,
+            )
+            .position_centered()
+            .resizable()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        canvas.set_draw_color(Color::BLACK);
+
+        let texture_creator = canvas.texture_creator();
+        let event_pump = sdl.event_pump()?;
+
+        Ok(Self {
+            title: title.to_owned(),
+            canvas,
+            texture_creator,
+            texture: None,
+            event_pump,
+            gamepads,
+            base_scale: filters.scale,
+            filters,
+            scale_mode,
+            palette: Palette::default(),
+            redraw: true,
+        })
+    }
+
+    fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        let window = self.canvas.window_mut();
+
+        let mode = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+
+        window.set_fullscreen(mode)?;
+        self.redraw = true;
+
+        Ok(())
+    }
+}
+
+// Where a frame of frame_w x frame_h CHIP-8 pixels goes inside a window of
+// out_w x out_h, as (x, y, width, height).
+fn viewport(
+    mode: ScaleMode,
+    frame_w: u32,
+    frame_h: u32,
+    out_w: u32,
+    out_h: u32,
+) -> (i32, i32, u32, u32) {
+    let (width, height) = match mode {
+        ScaleMode::Stretch => (out_w, out_h),
+        ScaleMode::Integer if out_w >= frame_w && out_h >= frame_h => {
+            let factor = (out_w / frame_w).min(out_h / frame_h);
+            (frame_w * factor, frame_h * factor)
+        }
+        // Integer scaling falls back to this when the window is too small
+        _ => {
+            if out_w as u64 * frame_h as u64 > out_h as u64 * frame_w as u64 {
+                (out_h * frame_w / frame_h, out_h)
+            } else {
+                (out_w, out_w * frame_h / frame_w)
+            }
+        }
+    };
+
+    (
+        ((out_w - width) / 2) as i32,
+        ((out_h - height) / 2) as i32,
+        width.max(1),
+        height.max(1),
+    )
+}
+
+impl Renderer for SdlRenderer {
+    fn present(&mut self, frame: &FrameView) -> Result<(), String> {
+        let (out_w, out_h) = self.canvas.output_size()?;
+        let (x, y, width, height) = viewport(
+            self.scale_mode,
+            frame.width as u32,
+            frame.height as u32,
+            out_w,
+            out_h,
+        );
+
+        // In integer mode the filters run at the final size, so scanlines and
+        // grid lines line up with the host pixels. This is recomputed every
+        // frame since both the window and the CHIP-8 resolution can change.
+        let scale = match self.scale_mode {
+            ScaleMode::Integer => (width as usize / frame.width).max(1),
+            _ => self.base_scale,
+        };
+
+        if scale != self.filters.scale {
+            self.filters.scale = scale;
+            self.redraw = true;
+        }
+
+        let stale = match &self.texture {
+            Some((_, w, h)) => *w != frame.width * scale || *h != frame.height * scale,
+            None => true,
+        };
+
+        // When nothing changed the texture still holds this frame
+        if stale || self.redraw || !frame.dirty.is_empty() {
+            let image = self
+                .filters
+                .process(frame.brightness, frame.width, frame.height, self.palette);
+
+            // The texture only has to be recreated when the output size changes
+            if stale {
+                let texture = self
+                    .texture_creator
+                    .create_texture_streaming(
+                        PixelFormatEnum::RGB24,
+                        image.width as u32,
+                        image.height as u32,
+                    )
+                    .map_err(|e| e.to_string())?;
+
+                self.texture = Some((texture, image.width, image.height));
+            }
+
+            let (texture, _, _) = self.texture.as_mut().unwrap();
+
+            texture
+                .update(None, &image.to_rgb24(), image.width * 3)
+                .map_err(|e| e.to_string())?;
+
+            self.redraw = false;
+        }
+
+        let (texture, _, _) = self.texture.as_ref().unwrap();
+
+        self.canvas.clear();
+        self.canvas
+            .copy(texture, None, Rect::new(x, y, width, height))?;
+        self.canvas.present();
+
+        Ok(())
+    }
+
+    fn resize(&mut self, _width: usize, _height: usize) -> Result<(), String> {
+        // The texture and the viewport follow the frame size on the next present
+        self.texture = None;
+        Ok(())
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.redraw = true;
+    }
+
+    fn set_status(&mut self, status: &str) {
+        let title = format!("{} - {}", self.title, status);
+
+        if let Err(e) = self.canvas.window_mut().set_title(&title) {
+            warn!("Failed to set the window title: {}", e);
+        }
+    }
+
+    fn poll_events(&mut self) -> Vec<HostEvent> {
+        let mut events = Vec::new();
+        let mut toggle_fullscreen = false;
+
+        for event in self.event_pump.poll_iter() {
+            if self.gamepads.handle(&event, &mut events) {
+                continue;
+            }
+
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => events.push(HostEvent::Quit),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    repeat: false,
+                    ..
+                } => toggle_fullscreen = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    repeat: false,
+                    ..
+                } => {
+                    self.scale_mode = self.scale_mode.next();
+                    self.redraw = true;
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                } => self.redraw = true,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } => events.push(HostEvent::KeyDown(keycode.name())),
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => events.push(HostEvent::KeyUp(keycode.name())),
+                _ => {}
+            }
+        }
+
+        if toggle_fullscreen {
+            if let Err(e) = self.toggle_fullscreen() {
+                warn!("Failed to toggle fullscreen: {}", e);
+            }
+        }
+
+        events
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::HashMap,
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers,
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use super::{FrameView, HostEvent, Palette, Renderer};
+use crate::phosphor::FULL_BRIGHTNESS;
+
+// Characters used for the pixel brightness, from off to fully lit.
+const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+// Longest key hold the settings accept, in milliseconds, see
+// TerminalRenderer::new
+pub const MAX_KEY_HOLD_MS: u64 = 2000;
+
+// Draws the framebuffer with block characters, one character per pixel.
+pub struct TerminalRenderer {
+    out: Box<dyn Write>,
+    // Whether this owns the terminal, which is put back as it was on drop
+    terminal: bool,
+    palette: Palette,
+    full_redraw: bool,
+    // The status line goes right below the frame
+    status_row: u16,
+    // The terminal speaks the kitty keyboard protocol, so it reports key
+    // releases and tells the numeric keypad from the other digits
+    kitty_keyboard: bool,
+    key_hold: Duration,
+    held_keys: HashMap<String, Instant>,
+}
+
+impl TerminalRenderer {
+    // Most terminals only report key presses. Without a release event a key
+    // counts as held until it has not been repeated for `key_hold`, which has
+    // to be longer than the delay before the keyboard starts repeating.
+    pub fn new(key_hold: Duration) -> Result<Self, String> {
+        let mut out = stdout();
+
+        terminal::enable_raw_mode().map_err(|e| e.to_string())?;
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))
+            .map_err(|e| e.to_string())?;
+
+        let kitty_keyboard = terminal::supports_keyboard_enhancement().unwrap_or(false)
+            && execute!(
+                out,
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                        | KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                )
+            )
+            .is_ok();
+
+        Ok(Self {
+            out: Box::new(out),
+            terminal: true,
+            palette: Palette::default(),
+            full_redraw: true,
+            status_row: 0,
+            kitty_keyboard,
+            key_hold,
+            held_keys: HashMap::new(),
+        })
+    }
+
+    // Writes the escape sequences to `out` and leaves the terminal alone, so
+    // there is no input either. For benchmarks and tests.
+    pub fn headless(out: impl Write + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            terminal: false,
+            palette: Palette::default(),
+            full_redraw: true,
+            status_row: 0,
+            kitty_keyboard: false,
+            key_hold: Duration::ZERO,
+            held_keys: HashMap::new(),
+        }
+    }
+}
+
+impl Renderer for TerminalRenderer {
+    fn present(&mut self, frame: &FrameView) -> Result<(), String> {
+        if !self.full_redraw && frame.dirty.is_empty() {
+            return Ok(());
+        }
+
+        // Only the changed span of the changed rows is sent, which keeps
+        // the output small on slow links.
+        let (start, end, rows): (usize, usize, Vec<usize>) = match frame.dirty.bounds() {
+            Some(bounds) if !self.full_redraw => (
+                bounds.x,
+                (bounds.x + bounds.width).min(frame.width),
+                frame.dirty.rows().filter(|&y| y < frame.height).collect(),
+            ),
+            _ => (0, frame.width, (0..frame.height).collect()),
+        };
+
+        queue!(
+            self.out,
+            SetForegroundColor(rgb(self.palette.on)),
+            SetBackgroundColor(rgb(self.palette.off))
+        )
+        .map_err(|e| e.to_string())?;
+
+        for y in rows {
+            let row: String = frame.brightness[y * frame.width + start..y * frame.width + end]
+                .iter()
+                .map(|&level| {
+                    let level = level as usize;
+                    SHADES[(level * (SHADES.len() - 1)).div_ceil(FULL_BRIGHTNESS as usize)]
+                })
+                .collect();
+
+            queue!(self.out, MoveTo(start as u16, y as u16), Print(row))
+                .map_err(|e| e.to_string())?;
+        }
+
+        self.full_redraw = false;
+
+        queue!(self.out, ResetColor).map_err(|e| e.to_string())?;
+        self.out.flush().map_err(|e| e.to_string())
+    }
+
+    fn resize(&mut self, _width: usize, height: usize) -> Result<(), String> {
+        self.full_redraw = true;
+        self.status_row = height as u16;
+        execute!(self.out, Clear(ClearType::All)).map_err(|e| e.to_string())
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.full_redraw = true;
+    }
+
+    fn misreads_key(&self, name: &str) -> bool {
+        !self.kitty_keyboard && name.to_lowercase().starts_with("keypad ")
+    }
+
+    fn set_status(&mut self, status: &str) {
+        let _ = execute!(
+            self.out,
+            MoveTo(0, self.status_row),
+            ResetColor,
+            Print(status),
+            Clear(ClearType::UntilNewLine)
+        );
+    }
+
+    fn poll_events(&mut self) -> Vec<HostEvent> {
+        let mut events = Vec::new();
+
+        if !self.terminal {
+            return events;
+        }
+
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            if let Ok(Event::Key(key)) = event::read() {
+                let ctrl_c = key.code == KeyCode::Char('c')
+                    && key.modifiers.contains(KeyModifiers::CONTROL);
+
+                let pressed = key.kind != KeyEventKind::Release;
+
+                if ctrl_c || key.code == KeyCode::Esc {
+                    events.push(HostEvent::Quit);
+                } else if let Some(name) = key_name(&key) {
+                    if !pressed {
+                        if self.held_keys.remove(&name).is_some() {
+                            events.push(HostEvent::KeyUp(name));
+                        }
+                    } else if self.held_keys.insert(name.clone(), Instant::now()).is_none() {
+                        events.push(HostEvent::KeyDown(name));
+                    }
+                }
+            }
+        }
+
+        if !self.kitty_keyboard {
+            let now = Instant::now();
+
+            self.held_keys.retain(|name, last_seen| {
+                let held = now.duration_since(*last_seen) < self.key_hold;
+
+                if !held {
+                    events.push(HostEvent::KeyUp(name.clone()));
+                }
+
+                held
+            });
+        }
+
+        events
+    }
+}
+
+impl Drop for TerminalRenderer {
+    fn drop(&mut self) {
+        if !self.terminal {
+            return;
+        }
+
+        if self.kitty_keyboard {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+
+        let _ = execute!(self.out, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Names keys the way SDL2 does, so both frontends can share a Keymap. Only
+// the kitty keyboard protocol marks the keys of the numeric keypad, other
+// terminals send them as plain digits.
+fn key_name(key: &KeyEvent) -> Option<String> {
+    let keypad = key.state.contains(KeyEventState::KEYPAD);
+
+    let name = match key.code {
+        KeyCode::Char(c) if keypad => format!("Keypad {}", c),
+        KeyCode::Enter if keypad => "Keypad Enter".to_owned(),
+        KeyCode::Char(' ') => "Space".to_owned(),
+        KeyCode::Char(c) => c.to_uppercase().to_string(),
+        KeyCode::Enter => "Return".to_owned(),
+        KeyCode::Tab => "Tab".to_owned(),
+        KeyCode::Backspace => "Backspace".to_owned(),
+        KeyCode::Left => "Left".to_owned(),
+        KeyCode::Right => "Right".to_owned(),
+        KeyCode::Up => "Up".to_owned(),
+        KeyCode::Down => "Down".to_owned(),
+        KeyCode::F(n) => format!("F{}", n),
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+fn rgb(color: u32) -> Color {
+    Color::Rgb {
+        r: (color >> 16) as u8,
+        g: (color >> 8) as u8,
+        b: color as u8,
+    }
+}
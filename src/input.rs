@@ -0,0 +1,61 @@
+// State of the 16 key hex keypad. Besides which keys are held it keeps the
+// press and release edges seen since they were last consumed, so instructions
+// can react to a key going down or coming back up even if both happened
+// between two instructions.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Keypad {
+    held: u16,
+    pressed: u16,
+    released: u16,
+}
+
+impl Keypad {
+    pub fn key_down(&mut self, key: u8) {
+        let bit = 1 << (key & 0xF);
+
+        if self.held & bit == 0 {
+            self.held |= bit;
+            self.pressed |= bit;
+        }
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        let bit = 1 << (key & 0xF);
+
+        if self.held & bit != 0 {
+            self.held &= !bit;
+            self.released |= bit;
+        }
+    }
+
+    pub fn is_down(&self, key: u8) -> bool {
+        self.held & (1 << (key & 0xF)) != 0
+    }
+
+    pub fn was_released(&self, key: u8) -> bool {
+        self.released & (1 << (key & 0xF)) != 0
+    }
+
+    // Lowest key pressed since the edges were last cleared.
+    pub fn first_pressed(&self) -> Option<u8> {
+        (0..16).find(|&key| self.pressed & (1 << key) != 0)
+    }
+
+    pub fn clear_edges(&mut self) {
+        self.pressed = 0;
+        self.released = 0;
+    }
+
+    // Held keys, press edges and release edges, for save states.
+    pub fn to_bits(self) -> [u16; 3] {
+        [self.held, self.pressed, self.released]
+    }
+
+    pub fn from_bits(bits: [u16; 3]) -> Self {
+        Self {
+            held: bits[0],
+            pressed: bits[1],
+            released: bits[2],
+        }
+    }
+}
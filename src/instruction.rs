@@ -0,0 +1,295 @@
+use std::fmt;
+
+// Decoded CHIP-8 instructions. The CPU, the disassembler and the assembler all
+// go through this, so they can not disagree on what an opcode means.
+//
+// Registers are register numbers (0 to F), addresses are 12 bits. The
+// mnemonics follow Cowgod's technical reference ("LD V1, 0x20", "DRW V0, V1, 5").
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Sys(u16),               // 0nnn, machine code routine, ignored
+    Cls,                    // 00E0
+    Ret,                    // 00EE
+    Jump(u16),              // 1nnn
+    Call(u16),              // 2nnn
+    SkipEqImm(u8, u8),      // 3xnn
+    SkipNeImm(u8, u8),      // 4xnn
+    SkipEqReg(u8, u8),      // 5xy0
+    LoadImm(u8, u8),        // 6xnn
+    AddImm(u8, u8),         // 7xnn
+    Move(u8, u8),           // 8xy0
+    Or(u8, u8),             // 8xy1
+    And(u8, u8),            // 8xy2
+    Xor(u8, u8),            // 8xy3
+    AddReg(u8, u8),         // 8xy4
+    Sub(u8, u8),            // 8xy5
+    ShiftRight(u8, u8),     // 8xy6
+    SubN(u8, u8),           // 8xy7
+    ShiftLeft(u8, u8),      // 8xyE
+    SkipNeReg(u8, u8),      // 9xy0
+    LoadIndex(u16),         // Annn
+    JumpV0(u16),            // Bnnn
+    Random(u8, u8),         // Cxnn
+    Draw(u8, u8, u8),       // Dxyn
+    SkipKey(u8),            // Ex9E
+    SkipNoKey(u8),          // ExA1
+    LoadDelay(u8),          // Fx07
+    WaitKey(u8),            // Fx0A
+    SetDelay(u8),           // Fx15
+    SetSound(u8),           // Fx18
+    AddIndex(u8),           // Fx1E
+    LoadFont(u8),           // Fx29
+    StoreBcd(u8),           // Fx33
+    Store(u8),              // Fx55
+    Load(u8),               // Fx65
+    LoadAudio,              // F002, XO-CHIP
+    SetPitch(u8),           // Fx3A, XO-CHIP
+    Unknown(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Self {
+        let x = ((opcode >> 8) & 0xF) as u8;
+        let y = ((opcode >> 4) & 0xF) as u8;
+        let n = (opcode & 0xF) as u8;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+
+        match opcode >> 12 {
+            0x0 => match opcode {
+                0x00E0 => Instruction::Cls,
+                0x00EE => Instruction::Ret,
+                _ => Instruction::Sys(nnn),
+            },
+            0x1 => Instruction::Jump(nnn),
+            0x2 => Instruction::Call(nnn),
+            0x3 => Instruction::SkipEqImm(x, nn),
+            0x4 => Instruction::SkipNeImm(x, nn),
+            0x5 if n == 0 => Instruction::SkipEqReg(x, y),
+            0x6 => Instruction::LoadImm(x, nn),
+            0x7 => Instruction::AddImm(x, nn),
+            0x8 => match n {
+                0x0 => Instruction::Move(x, y),
+                0x1 => Instruction::Or(x, y),
+                0x2 => Instruction::And(x, y),
+                0x3 => Instruction::Xor(x, y),
+                0x4 => Instruction::AddReg(x, y),
+                0x5 => Instruction::Sub(x, y),
+                0x6 => Instruction::ShiftRight(x, y),
+                0x7 => Instruction::SubN(x, y),
+                0xE => Instruction::ShiftLeft(x, y),
+                _ => Instruction::Unknown(opcode),
+            },
+            0x9 if n == 0 => Instruction::SkipNeReg(x, y),
+            0xA => Instruction::LoadIndex(nnn),
+            0xB => Instruction::JumpV0(nnn),
+            0xC => Instruction::Random(x, nn),
+            0xD => Instruction::Draw(x, y, n),
+            0xE => match nn {
+                0x9E => Instruction::SkipKey(x),
+                0xA1 => Instruction::SkipNoKey(x),
+                _ => Instruction::Unknown(opcode),
+            },
+            0xF => match nn {
+                0x02 if x == 0 => Instruction::LoadAudio,
+                0x07 => Instruction::LoadDelay(x),
+                0x0A => Instruction::WaitKey(x),
+                0x15 => Instruction::SetDelay(x),
+                0x18 => Instruction::SetSound(x),
+                0x1E => Instruction::AddIndex(x),
+                0x29 => Instruction::LoadFont(x),
+                0x33 => Instruction::StoreBcd(x),
+                0x55 => Instruction::Store(x),
+                0x65 => Instruction::Load(x),
+                0x3A => Instruction::SetPitch(x),
+                _ => Instruction::Unknown(opcode),
+            },
+            _ => Instruction::Unknown(opcode),
+        }
+    }
+
+    pub fn encode(self) -> u16 {
+        let xy = |prefix: u16, x: u8, y: u8, n: u16| {
+            prefix << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | n
+        };
+        let xnn = |prefix: u16, x: u8, nn: u8| prefix << 12 | (x as u16 & 0xF) << 8 | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | (x as u16 & 0xF) << 8 | nn;
+
+        match self {
+            Instruction::Sys(nnn) => nnn & 0xFFF,
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::Jump(nnn) => 0x1000 | (nnn & 0xFFF),
+            Instruction::Call(nnn) => 0x2000 | (nnn & 0xFFF),
+            Instruction::SkipEqImm(x, nn) => xnn(0x3, x, nn),
+            Instruction::SkipNeImm(x, nn) => xnn(0x4, x, nn),
+            Instruction::SkipEqReg(x, y) => xy(0x5, x, y, 0x0),
+            Instruction::LoadImm(x, nn) => xnn(0x6, x, nn),
+            Instruction::AddImm(x, nn) => xnn(0x7, x, nn),
+            Instruction::Move(x, y) => xy(0x8, x, y, 0x0),
+            Instruction::Or(x, y) => xy(0x8, x, y, 0x1),
+            Instruction::And(x, y) => xy(0x8, x, y, 0x2),
+            Instruction::Xor(x, y) => xy(0x8, x, y, 0x3),
+            Instruction::AddReg(x, y) => xy(0x8, x, y, 0x4),
+            Instruction::Sub(x, y) => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xy(0x8, x, y, 0x6),
+            Instruction::SubN(x, y) => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xy(0x8, x, y, 0xE),
+            Instruction::SkipNeReg(x, y) => xy(0x9, x, y, 0x0),
+            Instruction::LoadIndex(nnn) => 0xA000 | (nnn & 0xFFF),
+            Instruction::JumpV0(nnn) => 0xB000 | (nnn & 0xFFF),
+            Instruction::Random(x, nn) => xnn(0xC, x, nn),
+            Instruction::Draw(x, y, n) => xy(0xD, x, y, n as u16 & 0xF),
+            Instruction::SkipKey(x) => xnn(0xE, x, 0x9E),
+            Instruction::SkipNoKey(x) => xnn(0xE, x, 0xA1),
+            Instruction::LoadDelay(x) => fx(x, 0x07),
+            Instruction::WaitKey(x) => fx(x, 0x0A),
+            Instruction::SetDelay(x) => fx(x, 0x15),
+            Instruction::SetSound(x) => fx(x, 0x18),
+            Instruction::AddIndex(x) => fx(x, 0x1E),
+            Instruction::LoadFont(x) => fx(x, 0x29),
+            Instruction::StoreBcd(x) => fx(x, 0x33),
+            Instruction::Store(x) => fx(x, 0x55),
+            Instruction::Load(x) => fx(x, 0x65),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::SetPitch(x) => fx(x, 0x3A),
+            Instruction::Unknown(opcode) => opcode,
+        }
+    }
+
+    // Builds an instruction from a mnemonic and its operands, the way they
+    // are written in assembly ("LD", ["V1", "0x20"]). Numbers are already
+    // resolved by the caller, see asm.rs.
+    pub fn parse(mnemonic: &str, operands: &[Operand]) -> Result<Self, String> {
+        use Operand::*;
+
+        let mnemonic = mnemonic.to_uppercase();
+
+        let instruction = match (mnemonic.as_str(), operands) {
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Ret,
+            ("SYS", [Number(nnn)]) => Instruction::Sys(address(*nnn)?),
+            ("JP", [Number(nnn)]) => Instruction::Jump(address(*nnn)?),
+            ("JP", [Register(0), Number(nnn)]) => Instruction::JumpV0(address(*nnn)?),
+            ("CALL", [Number(nnn)]) => Instruction::Call(address(*nnn)?),
+            ("SE", [Register(x), Number(nn)]) => Instruction::SkipEqImm(*x, byte(*nn)?),
+            ("SE", [Register(x), Register(y)]) => Instruction::SkipEqReg(*x, *y),
+            ("SNE", [Register(x), Number(nn)]) => Instruction::SkipNeImm(*x, byte(*nn)?),
+            ("SNE", [Register(x), Register(y)]) => Instruction::SkipNeReg(*x, *y),
+            ("LD", [Register(x), Number(nn)]) => Instruction::LoadImm(*x, byte(*nn)?),
+            ("LD", [Register(x), Register(y)]) => Instruction::Move(*x, *y),
+            ("LD", [Index, Number(nnn)]) => Instruction::LoadIndex(address(*nnn)?),
+            ("LD", [Register(x), Delay]) => Instruction::LoadDelay(*x),
+            ("LD", [Register(x), Key]) => Instruction::WaitKey(*x),
+            ("LD", [Delay, Register(x)]) => Instruction::SetDelay(*x),
+            ("LD", [Sound, Register(x)]) => Instruction::SetSound(*x),
+            ("LD", [Font, Register(x)]) => Instruction::LoadFont(*x),
+            ("LD", [Bcd, Register(x)]) => Instruction::StoreBcd(*x),
+            ("LD", [IndexMemory, Register(x)]) => Instruction::Store(*x),
+            ("LD", [Register(x), IndexMemory]) => Instruction::Load(*x),
+            ("LD", [Pitch, Register(x)]) => Instruction::SetPitch(*x),
+            ("AUDIO", []) => Instruction::LoadAudio,
+            ("ADD", [Register(x), Number(nn)]) => Instruction::AddImm(*x, byte(*nn)?),
+            ("ADD", [Register(x), Register(y)]) => Instruction::AddReg(*x, *y),
+            ("ADD", [Index, Register(x)]) => Instruction::AddIndex(*x),
+            ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+            ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+            ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+            ("SUB", [Register(x), Register(y)]) => Instruction::Sub(*x, *y),
+            ("SUBN", [Register(x), Register(y)]) => Instruction::SubN(*x, *y),
+            // Vy is optional since some interpreters only shift Vx
+            ("SHR", [Register(x)]) => Instruction::ShiftRight(*x, *x),
+            ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight(*x, *y),
+            ("SHL", [Register(x)]) => Instruction::ShiftLeft(*x, *x),
+            ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft(*x, *y),
+            ("RND", [Register(x), Number(nn)]) => Instruction::Random(*x, byte(*nn)?),
+            ("DRW", [Register(x), Register(y), Number(n)]) if *n <= 0xF => {
+                Instruction::Draw(*x, *y, *n as u8)
+            }
+            ("SKP", [Register(x)]) => Instruction::SkipKey(*x),
+            ("SKNP", [Register(x)]) => Instruction::SkipNoKey(*x),
+            (
+                "CLS" | "RET" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR"
+                | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP"
+                | "SKNP" | "AUDIO",
+                _,
+            ) => return Err(format!("invalid operands for {}", mnemonic)),
+            _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+        };
+
+        Ok(instruction)
+    }
+}
+
+// An operand in assembly source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(u8), // V0 to VF
+    Index,        // I
+    IndexMemory,  // [I]
+    Delay,        // DT
+    Sound,        // ST
+    Key,          // K
+    Font,         // F
+    Bcd,          // B
+    Pitch,        // PITCH, XO-CHIP
+    Number(u16),
+}
+
+fn address(value: u16) -> Result<u16, String> {
+    if value > 0xFFF {
+        return Err(format!("address 0x{:X} does not fit in 12 bits", value));
+    }
+
+    Ok(value)
+}
+
+fn byte(value: u16) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("value 0x{:X} does not fit in a byte", value))
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::Sys(nnn) => write!(f, "SYS 0x{:03X}", nnn),
+            Instruction::Cls => write!(f, "CLS"),
+            Instruction::Ret => write!(f, "RET"),
+            Instruction::Jump(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SkipEqImm(x, nn) => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipNeImm(x, nn) => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipEqReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LoadImm(x, nn) => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::AddImm(x, nn) => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::Move(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadIndex(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JumpV0(nnn) => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::Random(x, nn) => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNoKey(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddIndex(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::StoreBcd(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::Store(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::Load(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::SetPitch(x) => write!(f, "LD PITCH, V{:X}", x),
+            // Not an instruction, written as data so it assembles back
+            Instruction::Unknown(opcode) => write!(f, "DW 0x{:04X}", opcode),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crate::gpu::Hotkey;
+
+// Maps host keys to the 16 keys of the CHIP-8 hex keypad:
+//
+//   1 2 3 C
+//   4 5 6 D
+//   7 8 9 E
+//   A 0 B F
+//
+// Host keys are identified by name, using the SDL2 key names ("Q", "1",
+// "Keypad 7", "Space", "Left"...) so every frontend shares the same maps.
+// Game controller buttons live in the same map as "Pad " followed by the
+// SDL2 button name ("Pad a", "Pad dpup"...). Names are compared without case.
+
+// CHIP-8 keys in keypad order, row by row
+const KEYPAD_ORDER: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+// Host keys laid out like the keypad above, row by row
+const QWERTY: [&str; 16] = [
+    "1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V",
+];
+const AZERTY: [&str; 16] = [
+    "1", "2", "3", "4", "A", "Z", "E", "R", "Q", "S", "D", "F", "W", "X", "C", "V",
+];
+const DVORAK: [&str; 16] = [
+    "1", "2", "3", "4", "'", ",", ".", "P", "A", "O", "E", "U", ";", "Q", "J", "K",
+];
+
+// The numeric keypad keeps the digits where they are, the operators stand in
+// for A to F.
+const NUMPAD: [(&str, u8); 16] = [
+    ("Keypad 0", 0x0),
+    ("Keypad 1", 0x1),
+    ("Keypad 2", 0x2),
+    ("Keypad 3", 0x3),
+    ("Keypad 4", 0x4),
+    ("Keypad 5", 0x5),
+    ("Keypad 6", 0x6),
+    ("Keypad 7", 0x7),
+    ("Keypad 8", 0x8),
+    ("Keypad 9", 0x9),
+    ("Keypad /", 0xA),
+    ("Keypad *", 0xB),
+    ("Keypad -", 0xC),
+    ("Keypad +", 0xD),
+    ("Keypad Enter", 0xE),
+    ("Keypad .", 0xF),
+];
+
+// Controller layout every preset starts with, the D-pad on the usual
+// 2/4/6/8 movement keys.
+const GAMEPAD: [(&str, u8); 8] = [
+    ("Pad dpup", 0x2),
+    ("Pad dpleft", 0x4),
+    ("Pad dpright", 0x6),
+    ("Pad dpdown", 0x8),
+    ("Pad a", 0x5),
+    ("Pad b", 0x0),
+    ("Pad x", 0xA),
+    ("Pad y", 0xB),
+];
+
+// Keys the frontends keep for themselves besides the hotkeys: Escape quits,
+// F10 and F11 change how the window shows the frame.
+const RESERVED: [&str; 3] = ["escape", "f10", "f11"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keymap {
+    bindings: HashMap<String, u8>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset("qwerty").unwrap()
+    }
+}
+
+impl Keymap {
+    // qwerty (the default), azerty, dvorak or numpad
+    pub fn preset(name: &str) -> Option<Self> {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+
+        for (host_key, key) in GAMEPAD {
+            keymap.bind(host_key, key);
+        }
+
+        let layout = match name {
+            "qwerty" => QWERTY,
+            "azerty" => AZERTY,
+            "dvorak" => DVORAK,
+            "numpad" => {
+                for (host_key, key) in NUMPAD {
+                    keymap.bind(host_key, key);
+                }
+
+                return Some(keymap);
+            }
+            _ => return None,
+        };
+
+        for (host_key, key) in layout.iter().zip(KEYPAD_ORDER) {
+            keymap.bind(host_key, key);
+        }
+
+        Some(keymap)
+    }
+
+    pub fn bind(&mut self, host_key: &str, key: u8) {
+        self.bindings.insert(host_key.to_lowercase(), key & 0xF);
+    }
+
+    // Every bound host key, in lowercase.
+    pub fn host_keys(&self) -> impl Iterator<Item = &str> {
+        self.bindings.keys().map(String::as_str)
+    }
+
+    // The CHIP-8 key a host key is bound to, if any.
+    pub fn get(&self, host_key: &str) -> Option<u8> {
+        self.bindings.get(&host_key.to_lowercase()).copied()
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = String;
+
+    // Parses a preset name followed by optional overrides, separated by
+    // commas: "azerty", "qwerty,Space=5,Pad dpleft=7" or just "Up=2,Down=8".
+    // When the preset is left out the map starts from qwerty.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = s.split(',').map(str::trim).filter(|entry| !entry.is_empty()).peekable();
+
+        let mut keymap = match entries.peek() {
+            Some(first) if !first.contains('=') => {
+                let keymap = Keymap::preset(first)
+                    .ok_or_else(|| format!("unknown keymap preset '{}'", first))?;
+                entries.next();
+                keymap
+            }
+            _ => Keymap::default(),
+        };
+
+        for entry in entries {
+            let (host_key, key) = entry
+                .rsplit_once('=')
+                .ok_or_else(|| format!("invalid key binding '{}'", entry))?;
+
+            let key = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|&key| key <= 0xF)
+                .ok_or_else(|| format!("invalid CHIP-8 key '{}' in '{}'", key, entry))?;
+
+            // These never reach the keymap, so the binding would do nothing
+            let host_key = host_key.trim();
+            let reserved = RESERVED.contains(&host_key.to_lowercase().as_str());
+            if reserved || Hotkey::from_key(host_key).is_some() {
+                return Err(format!("'{}' is an emulator control and cannot be bound", host_key));
+            }
+
+            keymap.bind(host_key, key);
+        }
+
+        Ok(keymap)
+    }
+}
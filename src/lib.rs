@@ -0,0 +1,27 @@
+// The emulator core, its frontends and tools. The binary in main.rs puts them
+// together behind the command line, tests and benchmarks use them directly.
+
+#[macro_use]
+pub mod log;
+
+pub mod analysis;
+pub mod asm;
+pub mod audio;
+pub mod config;
+pub mod conformance;
+pub mod cpu;
+pub mod database;
+pub mod dirty;
+pub mod disasm;
+pub mod filters;
+pub mod gpu;
+pub mod input;
+pub mod instruction;
+pub mod keymap;
+pub mod movie;
+pub mod opcodes;
+pub mod phosphor;
+pub mod quirks;
+pub mod rewind;
+pub mod speed;
+pub mod state;
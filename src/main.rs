@@ -1,40 +1,787 @@
-mod cpu;
-mod gpu;
-mod opcodes;
+mod cli;
 
-use std::{sync::{Arc, Mutex}, thread::sleep, time::Duration};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
-use cpu::{CPU, SCREEN_HEIGHT, SCREEN_WIDTH};
-use crossterm::{terminal::ClearType, Command};
+use clap::Parser;
 
+use chip_8_emulator::{
+    analysis, asm,
+    audio::{self, AudioSink, NullSink, ToneGenerator},
+    config::{Config, Settings},
+    conformance::{self, Scoring, Verdict},
+    cpu::{self, CPU, MAX_INSTRUCTIONS_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH},
+    database, debug, disasm,
+    filters::FilterChain,
+    gpu::{self, FrameView, HostEvent, Hotkey, Renderer},
+    info,
+    keymap::Keymap,
+    log,
+    movie::{Movie, Player},
+    phosphor::Phosphor,
+    quirks::{Quirks, PROFILES},
+    rewind::{Rewind, REWIND_INTERVAL},
+    speed::{Speed, SpeedControl},
+    warn,
+};
+use cli::{Cli, Command, ConformanceArgs, EmulatorArgs, Frontend, RunArgs};
+
+// Frames an erased pixel keeps glowing for, unless configured otherwise.
+// INVADERS moves its sprites by erasing and redrawing them, so it looks a
+// lot better with a short fade.
+const DEFAULT_PHOSPHOR_FADE_FRAMES: u8 = 3;
+
+// Post-processing chain for the SDL2 window, see FilterChain::from_str.
+#[cfg(feature = "sdl")]
+const SDL_FILTERS: &str = "scale=10,scanlines=0.35,bloom=0.4,curvature=0.05";
+
+// How the SDL2 window fits the frame, unless configured otherwise.
+#[cfg(feature = "sdl")]
+const DEFAULT_SCALE_MODE: gpu::ScaleMode = gpu::ScaleMode::Integer;
+
+// Lets the left analog stick of game controllers act as the D-pad, unless
+// configured otherwise.
+#[cfg(feature = "sdl")]
+const DEFAULT_STICK_AS_DPAD: bool = true;
+
+// Milliseconds a key counts as held in terminals that only report presses,
+// unless configured otherwise. Longer than the 250 to 500 ms most keyboards
+// wait before they start repeating, or a held key would let go in between.
+const DEFAULT_KEY_HOLD_MS: u64 = 600;
+
+// Buzzer tone played while the sound timer runs.
+const AUDIO_TONE: audio::ToneSettings = audio::ToneSettings {
+    waveform: audio::Waveform::Square,
+    frequency: 440.0,
+    volume: 0.25,
+};
+
+// Captured frames are only scaled up, to 4x4 image pixels per CHIP-8 pixel,
+// with none of the window's filters on top.
+const CAPTURE_FILTERS: &str = "scale=4";
+
+// Seconds of history kept for rewinding, unless configured otherwise.
+const DEFAULT_REWIND_SECONDS: u64 = 10;
+
+fn main() {
+    let cli = Cli::parse();
+
+    log::set_level(cli.log_level);
+
+    let result = match cli.command {
+        Command::Run(args) => run(args, cli.config),
+        Command::Headless(mut args) => {
+            let overrides = args.settings();
+            emulate(args, Frontend::Headless, overrides, cli.config)
+        }
+        Command::Disasm { rom, output } => disassemble(&rom, output.as_deref()),
+        Command::Asm { source, output } => assemble(&source, &output),
+        Command::Info { rom } => info(&rom),
+        Command::Conformance(args) => conformance(args),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(mut args: RunArgs, config: Option<PathBuf>) -> Result<(), String> {
+    let overrides = args.settings();
+    emulate(args.emulator, args.frontend, overrides, config)
+}
+
+// The emulator itself, with the window, terminal or nothing at all as the
+// frontend. Returns once the ROM has run for the frames asked for, or the
+// user quits. `overrides` are the command line flags, they win over the
+// settings file.
+fn emulate(
+    mut args: EmulatorArgs,
+    frontend: Frontend,
+    overrides: Settings,
+    config: Option<PathBuf>,
+) -> Result<(), String> {
+    let config = Config::load(config)?;
 
-fn main() {   
     let mut cpu = CPU::new();
     cpu.initialize();
-    cpu.load_rom("./ROMS/INVADERS.ch8".to_owned());
+    let rom_info = cpu.load_rom(&args.rom)?;
+
+    info!("Loaded {} (SHA-1 {})", args.rom.display(), cpu.rom_hash);
+
+    match &rom_info {
+        Some(rom_info) => info!(
+            "Found {} in the ROM database, using the settings for {}",
+            rom_info.title, rom_info.platform
+        ),
+        None => warn!(
+            "{} is not in the ROM database (SHA-1 {}), using the default settings",
+            args.rom.display(),
+            cpu.rom_hash
+        ),
+    }
+
+    let mut settings = config.for_rom(&cpu.rom_hash).layer(overrides);
+
+    // The ROM database already set up the CPU, the user's settings win over it
+    if let Some(quirks) = settings.quirks {
+        cpu.quirks = quirks;
+    }
+
+    if let Some(seed) = args.seed {
+        cpu.reseed(seed);
+    }
+
+    if let Some(count) = settings.ipf {
+        cpu.instructions_per_frame = count as usize;
+    }
+
+    // The ROM's own layout only fills in when no keymap was asked for
+    let keymap = settings.keymap.take().unwrap_or_else(|| {
+        let mut keymap = Keymap::default();
+
+        for &(host_key, key) in rom_info.iter().flat_map(|rom_info| &rom_info.keys) {
+            keymap.bind(host_key, key);
+        }
+
+        keymap
+    });
+
+    // Headless runs as fast as it can, the output only depends on emulated time
+    let mut speed = SpeedControl::new(settings.speed.unwrap_or(if frontend == Frontend::Headless {
+        Speed::Unlimited
+    } else {
+        Speed::Multiplier(1.0)
+    }));
+
+    let mut renderer = create_renderer(&frontend, &settings)
+        .map_err(|e| format!("failed to start the '{}' frontend: {}", frontend, e))?;
+
+    // Like the numeric keypad of a terminal that sends it as plain digits
+    let mut misread: Vec<&str> = keymap
+        .host_keys()
+        .filter(|&host_key| renderer.misreads_key(host_key))
+        .collect();
+
+    if !misread.is_empty() {
+        misread.sort();
+
+        return Err(format!(
+            "the '{}' frontend can not tell these keys from others: {}, use another keymap",
+            frontend,
+            misread.join(", ")
+        ));
+    }
+
+    // A movie brings its own quirks, seed and clock, anything else would make the
+    // replay drift away from the recording.
+    let mut player = match &args.play {
+        Some(path) => {
+            let movie = Movie::load(path).map_err(|e| format!("failed to play the movie: {}", e))?;
+
+            if movie.rom_hash != cpu.rom_hash {
+                return Err(format!(
+                    "{} was recorded with a different ROM (SHA-1 {}, {} has {})",
+                    path.display(),
+                    movie.rom_hash,
+                    args.rom.display(),
+                    cpu.rom_hash
+                ));
+            }
+
+            cpu.quirks = movie.quirks;
+            cpu.reseed(movie.seed);
+            cpu.instructions_per_frame = movie.instructions_per_frame;
+
+            if frontend == Frontend::Headless && args.frames.is_none() {
+                args.frames = Some(movie.length);
+            }
+
+            Some(Player::new(movie))
+        }
+        None => None,
+    };
+
+    let mut movie = args
+        .record
+        .as_ref()
+        .map(|_| {
+            Movie::new(
+                cpu.rom_hash.clone(),
+                cpu.quirks,
+                cpu.seed,
+                cpu.instructions_per_frame,
+            )
+        });
 
-    //println!("\n{:?}",&cpu.game_memory);
-    let mut timer: f64 = 0.0;
+    // Jumping back in time or changing the clock would desync a movie, so
+    // rewind and the clock hotkeys are off around them
+    let fixed_clock = movie.is_some() || player.is_some();
 
-    loop {
-        timer += 0.01;
-        cpu.update();
+    let mut rewind = if !fixed_clock {
+        Rewind::new(settings.rewind_seconds.unwrap_or(DEFAULT_REWIND_SECONDS))
+    } else {
+        Rewind::new(0)
+    };
+    let mut rewinding = false;
 
-        for y in 0..SCREEN_HEIGHT {
-            for x in 0..SCREEN_WIDTH {
-                if cpu.framebuffer[y * SCREEN_WIDTH + x] {
-                    print!("█"); // Character representing set pixel
-                } else {
-                    print!(" "); // Character representing clear pixel
+    let mut phosphor = Phosphor::new(settings.phosphor.unwrap_or(DEFAULT_PHOSPHOR_FADE_FRAMES));
+
+    let mut output = settings.audio.as_deref().unwrap_or(default_audio(&frontend)).to_owned();
+    let mut playback = open_audio_output(&output, &args.rom, cpu.frame_count);
+
+    // While muted the playback sink is parked here and a NullSink takes its place
+    let mut muted: Option<Box<dyn AudioSink>> = None;
+
+    let mut wav = match &args.wav {
+        Some(path) => Some(
+            audio::WavSink::create(path, audio::SAMPLE_RATE)
+                .map_err(|e| format!("failed to create {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+
+    // Audio is generated per emulated frame, so every sink gets the same
+    // stream no matter how fast the frames are produced.
+    let tone_settings = audio::ToneSettings {
+        waveform: settings.waveform.unwrap_or(AUDIO_TONE.waveform),
+        frequency: settings.frequency.unwrap_or(AUDIO_TONE.frequency),
+        volume: settings.volume.unwrap_or(AUDIO_TONE.volume),
+    };
+    let mut tone = ToneGenerator::new(tone_settings, audio::SAMPLE_RATE);
+    let mut samples = [0.0; audio::SAMPLES_PER_FRAME];
+
+    let palette = settings
+        .palette
+        .or_else(|| rom_info.as_ref().and_then(|rom_info| rom_info.palette))
+        .unwrap_or_default();
+    renderer.set_palette(palette);
+    renderer
+        .resize(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .map_err(|e| format!("failed to set up the display: {}", e))?;
+
+    let mut status = String::new();
+
+    while args.frames.is_none_or(|frames| cpu.frame_count < frames) {
+        let frame_start = Instant::now();
+
+        let events = renderer.poll_events();
+
+        if events.contains(&HostEvent::Quit) {
+            break;
+        }
+
+        for event in events {
+            let (name, pressed) = match event {
+                HostEvent::KeyDown(name) => (name, true),
+                HostEvent::KeyUp(name) => (name, false),
+                HostEvent::Quit => continue,
+            };
+
+            // Hotkeys win over the keymap
+            match (Hotkey::from_key(&name), pressed) {
+                (Some(Hotkey::NextAudio), true) => {
+                    output = next_audio_output(&output).to_owned();
+                    let sink = open_audio_output(&output, &args.rom, cpu.frame_count);
+
+                    // A muted session stays muted, the new output waits with it
+                    let mut previous = match &mut muted {
+                        Some(parked) => std::mem::replace(parked, sink),
+                        None => std::mem::replace(&mut playback, sink),
+                    };
+
+                    if let Err(e) = previous.finish() {
+                        warn!("Failed to close the previous audio output: {}", e);
+                    }
+                }
+                (Some(Hotkey::ToggleMute), true) => match muted.take() {
+                    Some(sink) => playback = sink,
+                    None => muted = Some(std::mem::replace(&mut playback, Box::new(NullSink))),
+                },
+                (Some(Hotkey::SlowerClock | Hotkey::FasterClock), _) if fixed_clock => {}
+                (Some(Hotkey::SlowerClock), true) => {
+                    cpu.instructions_per_frame = clock_step(cpu.instructions_per_frame, false)
+                }
+                (Some(Hotkey::FasterClock), true) => {
+                    cpu.instructions_per_frame = clock_step(cpu.instructions_per_frame, true)
+                }
+                (Some(Hotkey::TogglePause), true) => speed.toggle_pause(),
+                (Some(Hotkey::Step), true) => speed.step(),
+                (Some(Hotkey::Slower), true) => speed.slower(),
+                (Some(Hotkey::Faster), true) => speed.faster(),
+                (Some(Hotkey::Rewind), _) => rewinding = pressed,
+                (Some(_), false) => {}
+                // The keypad belongs to the movie while one is playing
+                (None, _) if player.is_some() => {}
+                (None, _) => {
+                    if let Some(key) = keymap.get(&name) {
+                        if pressed {
+                            cpu.key_down(key);
+                        } else {
+                            cpu.key_up(key);
+                        }
+
+                        if let Some(movie) = &mut movie {
+                            movie.record(cpu.frame_count, key, pressed);
+                        }
+                    }
                 }
             }
-            println!();
         }
-        
-        //println!("\rCur Program Counter: {:?} | 0x{:X}",cpu.program_counter,cpu.cur_opcode);
-        sleep(Duration::from_millis(25));
-        crossterm::terminal::Clear(ClearType::All).execute_winapi().unwrap();
 
+        // While rewinding the game goes backwards one snapshot per frame.
+        // Once the history runs out it just holds still.
+        if rewinding {
+            if let Some(state) = rewind.step_back() {
+                cpu.load_state(state)
+                    .map_err(|e| format!("failed to rewind: {}", e))?;
+            }
+        } else if speed.take_frame() {
+            if let Some(playing) = &mut player {
+                for event in playing.events_for(cpu.frame_count) {
+                    if event.pressed {
+                        cpu.key_down(event.key);
+                    } else {
+                        cpu.key_up(event.key);
+                    }
+                }
+
+                // Past the end of the movie the keypad goes back to the player
+                if playing.finished(cpu.frame_count) {
+                    player = None;
+                }
+            }
+
+            cpu.run_frame()
+                .map_err(|e| format!("the program stopped at {}", e))?;
+
+            if cpu.frame_count.is_multiple_of(REWIND_INTERVAL) {
+                rewind.push(cpu.save_state());
+            }
+
+            // Sound only comes out of frames that were actually emulated, so
+            // it stays silent while paused or rewinding.
+            tone.fill(&mut samples, cpu.sound_active(), cpu.sound_pattern());
+
+            if let Err(e) = playback.queue(&samples) {
+                warn!("Audio output failed, sound is disabled: {}", e);
+                playback = Box::new(NullSink);
+            }
+
+            if let Some(wav) = &mut wav {
+                wav.queue(&samples)
+                    .map_err(|e| format!("failed to write the WAV file: {}", e))?;
+            }
+        }
+
+        let current_status = format!(
+            "{} instructions/frame, speed {}{}, sound {}{}",
+            cpu.instructions_per_frame,
+            speed.speed,
+            if speed.paused { ", paused" } else { "" },
+            output,
+            if muted.is_some() { " (muted)" } else { "" }
+        );
+
+        if current_status != status {
+            debug!("{}", current_status);
+            renderer.set_status(&current_status);
+            status = current_status;
+        }
+
+        let mut dirty = cpu.take_dirty();
+        let brightness = phosphor.update(&cpu.framebuffer, SCREEN_WIDTH, &mut dirty);
+
+        let frame = FrameView {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            brightness,
+            dirty: &dirty,
+        };
+
+        renderer
+            .present(&frame)
+            .map_err(|e| format!("failed to draw frame {}: {}", cpu.frame_count, e))?;
+
+        if let Some(duration) = speed.frame_duration() {
+            if let Some(rest) = duration.checked_sub(frame_start.elapsed()) {
+                sleep(rest);
+            }
+        }
+    }
+
+    if let (Some(mut movie), Some(path)) = (movie, &args.record) {
+        movie.length = cpu.frame_count;
+        movie
+            .save(path)
+            .map_err(|e| format!("failed to save the movie {}: {}", path.display(), e))?;
+    }
+
+    if let Some(mut wav) = wav {
+        wav.finish()
+            .map_err(|e| format!("failed to write the WAV file: {}", e))?;
+    }
+
+    // The wav output only has a valid file once it is finished
+    for sink in muted.iter_mut().chain([&mut playback]) {
+        sink.finish()
+            .map_err(|e| format!("failed to close the '{}' audio output: {}", output, e))?;
+    }
+
+    Ok(())
+}
+
+fn disassemble(rom: &Path, output: Option<&Path>) -> Result<(), String> {
+    let bytes = fs::read(rom).map_err(|e| format!("{}: {}", rom.display(), e))?;
+    let listing = disasm::disassemble(&bytes);
+
+    match output {
+        Some(path) => fs::write(path, listing).map_err(|e| format!("{}: {}", path.display(), e)),
+        None => {
+            print!("{}", listing);
+            Ok(())
+        }
+    }
+}
+
+fn assemble(source: &Path, output: &Path) -> Result<(), String> {
+    let text = fs::read_to_string(source).map_err(|e| format!("{}: {}", source.display(), e))?;
+    let rom = asm::assemble(&text).map_err(|e| format!("{}: {}", source.display(), e))?;
+
+    fs::write(output, &rom).map_err(|e| format!("{}: {}", output.display(), e))?;
+    info!("Wrote {} bytes to {}", rom.len(), output.display());
+
+    Ok(())
+}
+
+fn info(rom: &Path) -> Result<(), String> {
+    let bytes = fs::read(rom).map_err(|e| format!("{}: {}", rom.display(), e))?;
+    let hash = cpu::rom_hash(&bytes);
+    let analysis = analysis::analyze(&bytes).map_err(|e| format!("{} {}", rom.display(), e))?;
+
+    println!("File:   {}", rom.display());
+    println!("Size:   {} bytes", bytes.len());
+    println!("SHA-1:  {}", hash);
+
+    let mut code = format!(
+        "{} reachable instructions in {} words",
+        analysis.code.len(),
+        bytes.len() / 2
+    );
+
+    if analysis.computed_jumps > 0 {
+        code += &format!(", {} BNNN jumps not followed", analysis.computed_jumps);
+    }
+
+    if !analysis.unknown.is_empty() {
+        code += &format!(", {} unknown opcodes", analysis.unknown.len());
+    }
+
+    println!("Code:   {}", code);
+
+    let families: Vec<String> = analysis
+        .families()
+        .map(|family| match analysis.extensions.get(&family) {
+            Some(patterns) => {
+                format!("{} ({})", family, patterns.iter().copied().collect::<Vec<_>>().join(", "))
+            }
+            None => family.to_string(),
+        })
+        .collect();
+    println!("Uses:   {}", families.join(", "));
+
+    if analysis.high_accesses.is_empty() && analysis.unchecked_accesses.is_empty() {
+        println!("Memory: stays below 0x{:03X}", analysis::LAST_PROGRAM_ADDRESS + 1);
+    } else if analysis.high_accesses.is_empty() {
+        println!("Memory: no accesses past 0x{:03X} found", analysis::LAST_PROGRAM_ADDRESS);
+    } else {
+        println!("Memory: goes past 0x{:03X}", analysis::LAST_PROGRAM_ADDRESS);
+    }
+
+    for access in &analysis.high_accesses {
+        let verb = match access.access {
+            analysis::Access::Read => "reads",
+            analysis::Access::Write => "writes",
+            analysis::Access::Execute => "runs",
+        };
+
+        println!(
+            "        0x{:03X} {} {} 0x{:03X}-0x{:03X}",
+            access.at, access.pattern, verb, access.start, access.end
+        );
+    }
+
+    if !analysis.unchecked_accesses.is_empty() {
+        println!(
+            "        not checked: {} instructions use an I computed at run time",
+            analysis.unchecked_accesses.len()
+        );
+    }
+
+    match analysis.call_depth {
+        Some(0) => println!("Calls:  none"),
+        Some(depth) if analysis.call_stack_overflows() => println!(
+            "Calls:  nested {} deep, more than the {} levels of the stack",
+            depth,
+            analysis::STACK_DEPTH
+        ),
+        Some(depth) => println!("Calls:  nested {} deep", depth),
+        None => println!("Calls:  unbounded, a subroutine can call itself again before returning"),
+    }
+
+    for (pattern, (count, quirks)) in &analysis.quirky {
+        println!("Quirks: {} x{} ({})", pattern, count, quirks);
+    }
+
+    match database::lookup(&hash) {
+        Some(rom_info) => {
+            println!("Title:  {}", rom_info.title);
+
+            if !rom_info.authors.is_empty() {
+                println!("By:     {}", rom_info.authors.join(", "));
+            }
+
+            if let Some(release) = &rom_info.release {
+                println!("Year:   {}", release);
+            }
+
+            println!("For:    {}", rom_info.platform);
+            println!("Use:    --quirks {} --ipf {}", rom_info.quirks, rom_info.instructions_per_frame);
+        }
+        None => println!(
+            "Not in the ROM database, try --quirks {}",
+            analysis.suggested_profile()
+        ),
+    }
+
+    Ok(())
+}
+
+// Prints a matrix of every test and mode against the quirks profiles. Any
+// check that does not match its expected frame makes this fail, checks
+// without one are only reported.
+fn conformance(args: ConformanceArgs) -> Result<(), String> {
+    let profiles: Vec<&str> = if args.profiles.is_empty() {
+        PROFILES.to_vec()
+    } else {
+        args.profiles.iter().map(String::as_str).collect()
+    };
+
+    let mut rows = Vec::new();
+    let mut failures = Vec::new();
+    let mut saved = Vec::new();
+    let mut skipped = Vec::new();
+    let mut scored = 0;
+
+    for path in &args.roms {
+        let test = conformance::identify(path).ok_or_else(|| {
+            let names: Vec<&str> = conformance::SUITE.iter().map(|test| test.name).collect();
+            format!(
+                "{}: not a test suite ROM, the file name should contain one of {}",
+                path.display(),
+                names.join(", ")
+            )
+        })?;
+        if !test.supported {
+            skipped.push(format!(
+                "{}: skipped, the scoring of {} has not been tried on this ROM yet",
+                path.display(),
+                test.name
+            ));
+            continue;
+        }
+
+        let rom = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        for mode in test.modes {
+            let mut cells = Vec::new();
+
+            for &profile in &profiles {
+                if !mode.runs_under(profile) {
+                    cells.push("-");
+                    continue;
+                }
+
+                let name = conformance::check_name(test, mode, profile);
+                let expected_path = args.expected.join(format!("{}.png", name));
+
+                let quirks = Quirks::profile(profile).expect("profiles are checked by the parser");
+                let run = conformance::run(test, mode, &rom, quirks)
+                    .map_err(|e| format!("{} {}", path.display(), e))?;
+
+                // A saved frame only becomes the expected one once it has
+                // been looked at, so it is not scored against itself
+                if args.bless && test.scoring == Scoring::Frame {
+                    conformance::save_frame(&expected_path, &run.framebuffer)?;
+                    saved.push(expected_path);
+                    cells.push("saved");
+                    continue;
+                }
+
+                let expected = match test.scoring {
+                    Scoring::Frame => conformance::load_frame(&expected_path)?,
+                    _ => None,
+                };
+                scored += 1;
+
+                match conformance::score(test, &run, expected.as_ref()) {
+                    Verdict::Pass => cells.push("pass"),
+                    Verdict::Fail(reason) => {
+                        cells.push("FAIL");
+
+                        if test.scoring == Scoring::Frame && expected.is_none() {
+                            failures.push(format!(
+                                "{}: {} at {}, save one with --bless and check it by eye",
+                                name,
+                                reason,
+                                expected_path.display()
+                            ));
+                        } else {
+                            failures.push(format!("{}: {}", name, reason));
+                        }
+                    }
+                }
+            }
+
+            rows.push((format!("{} {}", test.name, mode.name).trim_end().to_owned(), cells));
+        }
+    }
+
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let print_row = |name: &str, cells: &[&str]| {
+        let cells: String = cells.iter().map(|cell| format!("{:8}", cell)).collect();
+        println!("{}", format!("{:width$}  {}", name, cells).trim_end());
+    };
+
+    print_row("", &profiles);
+    for (name, cells) in &rows {
+        print_row(name, cells);
+    }
+
+    for line in failures.iter().chain(&skipped) {
+        println!("{}", line);
+    }
+
+    if !saved.is_empty() {
+        println!(
+            "Saved {} frames to {}, check them by eye before keeping them",
+            saved.len(),
+            args.expected.display()
+        );
+    }
+
+    if !failures.is_empty() {
+        return Err(format!("{} of {} checks failed", failures.len(), scored));
+    }
+
+    Ok(())
+}
+
+// Next clock setting for the F3/F4 hotkeys. Fine steps at the low end where
+// one instruction more or less is noticeable, coarser ones above.
+fn clock_step(current: usize, faster: bool) -> usize {
+    let step = |count: usize| match count {
+        0..=19 => 1,
+        20..=99 => 5,
+        _ => 50,
+    };
+
+    let next = if faster {
+        current + step(current)
+    } else {
+        // The step of the range below, so going down retraces going up
+        current.saturating_sub(step(current.saturating_sub(1)))
+    };
+
+    next.clamp(1, MAX_INSTRUCTIONS_PER_FRAME)
+}
+
+fn default_audio(frontend: &Frontend) -> &'static str {
+    if cfg!(feature = "sdl") && *frontend != Frontend::Headless {
+        "sdl"
+    } else {
+        "null"
+    }
+}
+
+// The emulator keeps running silently when an output can not be opened.
+// The wav output writes a new file named after the ROM and the frame it
+// starts at, so switching back to it does not overwrite the last one.
+fn open_audio_output(output: &str, rom: &Path, frame: u64) -> Box<dyn AudioSink> {
+    let sink: Result<Box<dyn AudioSink>, String> = match output {
+        #[cfg(feature = "sdl")]
+        "sdl" => audio::SdlSink::new().map(|sink| Box::new(sink) as Box<dyn AudioSink>),
+        #[cfg(not(feature = "sdl"))]
+        "sdl" => Err("this build has no SDL2 support, rebuild with --features sdl".to_owned()),
+        "wav" => {
+            let stem = rom.file_stem().unwrap_or_default().to_string_lossy();
+            let path = PathBuf::from(format!("{}-{}.wav", stem, frame));
+
+            match audio::WavSink::create(&path, audio::SAMPLE_RATE) {
+                Ok(sink) => {
+                    info!("Writing the sound into {}", path.display());
+                    Ok(Box::new(sink))
+                }
+                Err(e) => Err(format!("{}: {}", path.display(), e)),
+            }
+        }
+        "null" => Ok(Box::new(NullSink)),
+        _ => Err("unknown audio output".to_owned()),
+    };
+
+    sink.unwrap_or_else(|e| {
+        warn!("Failed to open the '{}' audio output, sound is disabled: {}", output, e);
+        Box::new(NullSink)
+    })
+}
+
+fn next_audio_output(output: &str) -> &'static str {
+    let at = audio::OUTPUTS.iter().position(|&name| name == output).unwrap_or(0);
+    audio::OUTPUTS[(at + 1) % audio::OUTPUTS.len()]
+}
+
+// The filters and scale of `settings` replace the frontend's own
+// post-processing and size.
+fn create_renderer(frontend: &Frontend, settings: &Settings) -> Result<Box<dyn Renderer>, String> {
+    let filters = |default: &str| -> Result<FilterChain, String> {
+        let mut filters = match &settings.filters {
+            Some(filters) => filters.clone(),
+            None => default.parse()?,
+        };
+
+        if let Some(scale) = settings.scale {
+            filters.scale = scale as usize;
+        }
+
+        Ok(filters)
+    };
+
+    match frontend {
+        #[cfg(feature = "sdl")]
+        Frontend::Sdl => Ok(Box::new(gpu::SdlRenderer::new(
+            "CHIP-8",
+            SCREEN_WIDTH,
+            SCREEN_HEIGHT,
+            filters(SDL_FILTERS)?,
+            settings.scale_mode.unwrap_or(DEFAULT_SCALE_MODE),
+            settings.stick_as_dpad.unwrap_or(DEFAULT_STICK_AS_DPAD),
+        )?)),
+        #[cfg(not(feature = "sdl"))]
+        Frontend::Sdl => {
+            Err("this build has no SDL2 support, rebuild with --features sdl".to_owned())
+        }
+        Frontend::Terminal => Ok(Box::new(gpu::TerminalRenderer::new(Duration::from_millis(
+            settings.key_hold.unwrap_or(DEFAULT_KEY_HOLD_MS),
+        ))?)),
+        Frontend::Headless => Ok(Box::new(gpu::NullRenderer::default())),
+        Frontend::Capture(directory) => Ok(Box::new(gpu::CaptureRenderer::new(
+            directory.clone(),
+            filters(CAPTURE_FILTERS)?,
+        )?)),
     }
-    
-}
\ No newline at end of file
+}
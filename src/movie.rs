@@ -0,0 +1,187 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    cpu::{DEFAULT_INSTRUCTIONS_PER_FRAME, MAX_INSTRUCTIONS_PER_FRAME},
+    quirks::Quirks,
+};
+
+// Version 2 added the ipf line, readers of version 1 would choke on it
+const MAGIC: &str = "chip8-movie 2";
+const MAGIC_V1: &str = "chip8-movie 1";
+
+// A recorded session: every keypad change with the frame it happened on, plus
+// everything else needed to replay it exactly. Saved as plain text:
+//
+//   chip8-movie 2
+//   rom 0123456789abcdef0123456789abcdef01234567
+//   quirks key_wait_release=on,shift=off,...,logic=off
+//   seed 42
+//   ipf 10
+//   120 down 5
+//   128 up 5
+//   end 600
+//
+// A key change on frame N is applied before frame N runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom_hash: String,
+    pub quirks: Quirks,
+    pub seed: u64,
+    pub instructions_per_frame: usize,
+    pub events: Vec<KeyEvent>,
+    // Frames in the recording
+    pub length: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+impl Movie {
+    pub fn new(rom_hash: String, quirks: Quirks, seed: u64, instructions_per_frame: usize) -> Self {
+        Self {
+            rom_hash,
+            quirks,
+            seed,
+            instructions_per_frame,
+            events: Vec::new(),
+            length: 0,
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, key: u8, pressed: bool) {
+        self.events.push(KeyEvent {
+            frame,
+            key,
+            pressed,
+        });
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = Vec::new();
+
+        writeln!(out, "{}", MAGIC)?;
+        writeln!(out, "rom {}", self.rom_hash)?;
+        writeln!(out, "quirks {}", self.quirks)?;
+        writeln!(out, "seed {}", self.seed)?;
+        writeln!(out, "ipf {}", self.instructions_per_frame)?;
+
+        for event in &self.events {
+            let action = if event.pressed { "down" } else { "up" };
+            writeln!(out, "{} {} {:X}", event.frame, action, event.key)?;
+        }
+
+        writeln!(out, "end {}", self.length)?;
+
+        fs::write(path, out)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+
+        match lines.next() {
+            Some((_, MAGIC | MAGIC_V1)) => {}
+            _ => return Err("not a movie file".to_owned()),
+        }
+
+        // Version 1 movies, from before the clock was configurable, have no
+        // ipf line
+        let mut movie = Movie::new(
+            String::new(),
+            Quirks::default(),
+            0,
+            DEFAULT_INSTRUCTIONS_PER_FRAME,
+        );
+        let mut ended = false;
+
+        for (number, line) in lines.filter(|(_, line)| !line.is_empty()) {
+            let invalid = || format!("line {}: invalid entry '{}'", number, line);
+            let (first, rest) = line.split_once(' ').ok_or_else(invalid)?;
+
+            match first {
+                "rom" => movie.rom_hash = rest.trim().to_owned(),
+                "quirks" => {
+                    movie.quirks = rest.parse().map_err(|e| format!("line {}: {}", number, e))?
+                }
+                "seed" => movie.seed = rest.trim().parse().map_err(|_| invalid())?,
+                "ipf" => {
+                    let count = rest.trim().parse().map_err(|_| invalid())?;
+
+                    if !(1..=MAX_INSTRUCTIONS_PER_FRAME).contains(&count) {
+                        return Err(format!(
+                            "line {}: ipf must be between 1 and {}",
+                            number, MAX_INSTRUCTIONS_PER_FRAME
+                        ));
+                    }
+
+                    movie.instructions_per_frame = count;
+                }
+                "end" => {
+                    movie.length = rest.trim().parse().map_err(|_| invalid())?;
+                    ended = true;
+                }
+                _ => {
+                    let frame = first.parse().map_err(|_| invalid())?;
+                    let (action, key) = rest.trim().split_once(' ').ok_or_else(invalid)?;
+
+                    let pressed = match action {
+                        "down" => true,
+                        "up" => false,
+                        _ => return Err(invalid()),
+                    };
+                    let key = u8::from_str_radix(key.trim(), 16)
+                        .ok()
+                        .filter(|&key| key <= 0xF)
+                        .ok_or_else(invalid)?;
+
+                    movie.record(frame, key, pressed);
+                }
+            }
+        }
+
+        if !ended {
+            return Err("the movie has no end, the recording was cut short".to_owned());
+        }
+
+        Ok(movie)
+    }
+}
+
+// Feeds the key changes of a movie back frame by frame.
+pub struct Player {
+    movie: Movie,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Self {
+        Self { movie, next: 0 }
+    }
+
+    // Key changes to apply before `frame` runs.
+    pub fn events_for(&mut self, frame: u64) -> &[KeyEvent] {
+        let start = self.next;
+
+        while self.next < self.movie.events.len() && self.movie.events[self.next].frame <= frame {
+            self.next += 1;
+        }
+
+        &self.movie.events[start..self.next]
+    }
+
+    pub fn finished(&self, frame: u64) -> bool {
+        frame >= self.movie.length
+    }
+}
@@ -1,23 +1,32 @@
-use std::{arch::x86_64, f32::RADIX};
+use std::ops::Range;
 
-use rand::Rng;
+use crate::cpu::{KeyWait, CPU, MEMORY_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
 
-use crate::cpu::{CPU, SCREEN_HEIGHT, SCREEN_WIDTH};
+// Returns to the instruction after the call, the stack holds the address of
+// the 2NNN itself.
+pub fn opcode_0_0ee(cpu: &mut CPU) -> Result<(), String> {
+    if cpu.stack_pointer == 0 {
+        return Err("return with an empty stack".to_owned());
+    }
 
-pub fn opcode_0_0ee(cpu: &mut CPU) {
     cpu.stack_pointer -= 1;
-    cpu.program_counter = cpu.stack[cpu.stack_pointer as usize] as u16;
-    cpu.program_counter += 2;
+    cpu.program_counter = cpu.stack[cpu.stack_pointer as usize].wrapping_add(2);
+    Ok(())
 }
 
 pub fn opcode_1_nnn(cpu: &mut CPU, opcode: u16) {
     cpu.program_counter = opcode & 0x0FFF;
 }
 
-pub fn opcode_2_nnn(cpu: &mut CPU, opcode: u16) {
+pub fn opcode_2_nnn(cpu: &mut CPU, opcode: u16) -> Result<(), String> {
+    if cpu.stack_pointer as usize >= cpu.stack.len() {
+        return Err(format!("call with all {} stack levels in use", cpu.stack.len()));
+    }
+
     cpu.stack[cpu.stack_pointer as usize] = cpu.program_counter;
     cpu.stack_pointer += 1;
     cpu.program_counter = opcode & 0x0FFF;
+    Ok(())
 }
 
 pub fn opcode_3_xnn(cpu: &mut CPU, opcode: u16) {
@@ -50,6 +59,8 @@ pub fn opcode_5_xy0(cpu: &mut CPU, opcode: u16) {
     regy >>= 4; // Ex: 0x2
 
     if cpu.registers[regx as usize] == cpu.registers[regy as usize] {
+        cpu.program_counter += 4;
+    } else {
         cpu.program_counter += 2;
     }
 }
@@ -91,6 +102,10 @@ pub fn opcode_8_xy1(cpu: &mut CPU, opcode: u16) {
     let yval = cpu.registers[regy as usize];
 
     cpu.registers[regx as usize] |= yval;
+
+    if cpu.quirks.logic {
+        cpu.registers[0xF] = 0;
+    }
 }
 
 pub fn opcode_8_xy2(cpu: &mut CPU, opcode: u16) {
@@ -103,6 +118,10 @@ pub fn opcode_8_xy2(cpu: &mut CPU, opcode: u16) {
     let yval = cpu.registers[regy as usize];
 
     cpu.registers[regx as usize] &= yval;
+
+    if cpu.quirks.logic {
+        cpu.registers[0xF] = 0;
+    }
 }
 
 pub fn opcode_8_xy3(cpu: &mut CPU, opcode: u16) {
@@ -115,11 +134,15 @@ pub fn opcode_8_xy3(cpu: &mut CPU, opcode: u16) {
     let yval = cpu.registers[regy as usize];
 
     cpu.registers[regx as usize] ^= yval;
+
+    if cpu.quirks.logic {
+        cpu.registers[0xF] = 0;
+    }
 }
 
+// The arithmetic instructions write VF after the result, so with VF as VX
+// the flag is what is left.
 pub fn opcode_8_xy4(cpu: &mut CPU, opcode: u16) {
-    cpu.registers[0xF] = 0;
-
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
@@ -128,17 +151,13 @@ pub fn opcode_8_xy4(cpu: &mut CPU, opcode: u16) {
 
     let xval = cpu.registers[regx as usize];
     let yval = cpu.registers[regy as usize];
+    let (result, carry) = xval.overflowing_add(yval);
 
-    if yval > xval {
-        cpu.registers[0xF] = 1;
-    }
-
-    cpu.registers[regx as usize] += yval;
+    cpu.registers[regx as usize] = result;
+    cpu.registers[0xF] = carry as u8;
 }
 
 pub fn opcode_8_xy5(cpu: &mut CPU, opcode: u16) {
-    cpu.registers[0xF] = 1;
-
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
@@ -147,12 +166,10 @@ pub fn opcode_8_xy5(cpu: &mut CPU, opcode: u16) {
 
     let xval = cpu.registers[regx as usize];
     let yval = cpu.registers[regy as usize];
+    let (result, borrow) = xval.overflowing_sub(yval);
 
-    if yval > xval {
-        cpu.registers[0xF] = 0;
-    }
-
-    cpu.registers[regx as usize] -= yval;
+    cpu.registers[regx as usize] = result;
+    cpu.registers[0xF] = !borrow as u8;
 }
 
 pub fn opcode_8_xy6(cpu: &mut CPU, opcode: u16) {
@@ -162,14 +179,14 @@ pub fn opcode_8_xy6(cpu: &mut CPU, opcode: u16) {
     let mut regy = opcode & 0x00F0;
     regy >>= 4;
 
-    cpu.registers[regx as usize] = cpu.registers[regy as usize];
-    cpu.registers[regx as usize] >>= 1;
-    cpu.registers[0xF] = cpu.registers[regy as usize] & 0x0001;
+    let source = if cpu.quirks.shift { regx } else { regy };
+    let value = cpu.registers[source as usize];
+
+    cpu.registers[regx as usize] = value >> 1;
+    cpu.registers[0xF] = value & 0x01;
 }
 
 pub fn opcode_8_xy7(cpu: &mut CPU, opcode: u16) {
-    cpu.registers[0xF] = 1;
-
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
@@ -178,12 +195,10 @@ pub fn opcode_8_xy7(cpu: &mut CPU, opcode: u16) {
 
     let xval = cpu.registers[regx as usize];
     let yval = cpu.registers[regy as usize];
+    let (result, borrow) = yval.overflowing_sub(xval);
 
-    if yval > xval {
-        cpu.registers[0xF] = 0;
-    }
-
-    cpu.registers[regx as usize] = yval - xval;
+    cpu.registers[regx as usize] = result;
+    cpu.registers[0xF] = !borrow as u8;
 }
 
 pub fn opcode_8_xye(cpu: &mut CPU, opcode: u16) {
@@ -193,9 +208,11 @@ pub fn opcode_8_xye(cpu: &mut CPU, opcode: u16) {
     let mut regy = opcode & 0x00F0;
     regy >>= 4;
 
-    cpu.registers[regx as usize] = cpu.registers[regy as usize];
-    cpu.registers[regx as usize] <<= 1;
-    cpu.registers[0xF] = cpu.registers[regy as usize] >> 7;
+    let source = if cpu.quirks.shift { regx } else { regy };
+    let value = cpu.registers[source as usize];
+
+    cpu.registers[regx as usize] = value << 1;
+    cpu.registers[0xF] = value >> 7;
 }
 
 pub fn opcode_9_xy0(cpu: &mut CPU, opcode: u16) {
@@ -217,7 +234,10 @@ pub fn opcode_a_nnn(cpu: &mut CPU, opcode: u16) {
 }
 
 pub fn opcode_b_nnn(cpu: &mut CPU, opcode: u16) {
-    cpu.program_counter = (opcode & 0x0FFF) + cpu.registers[0] as u16;
+    // With the jump quirk the high nibble of NNN also picks the register
+    let reg = if cpu.quirks.jump { (opcode & 0x0F00) >> 8 } else { 0 };
+
+    cpu.program_counter = (opcode & 0x0FFF) + cpu.registers[reg as usize] as u16;
 }
 
 pub fn opcode_c_xnn(cpu: &mut CPU, opcode: u16) {
@@ -225,12 +245,12 @@ pub fn opcode_c_xnn(cpu: &mut CPU, opcode: u16) {
     regx >>= 8;
 
     let nn: u16 = opcode & 0x00FF;
-    let rng: u8 = rand::thread_rng().gen();
+    let rng: u8 = cpu.rng.next_u8();
 
     cpu.registers[regx as usize] = rng & nn as u8;
 }
 
-pub fn opcode_d_xyn(cpu: &mut CPU, opcode: u16) {
+pub fn opcode_d_xyn(cpu: &mut CPU, opcode: u16) -> Result<(), String> {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
@@ -241,27 +261,42 @@ pub fn opcode_d_xyn(cpu: &mut CPU, opcode: u16) {
     let y = cpu.registers[regy as usize];
     let height = (opcode & 0x000F) as u8;
 
-    let mut pixel: u8;
+    // The starting position always wraps, the rest of the sprite only does
+    // with the wrap quirk and is clipped at the edges otherwise
+    let x = x as usize % SCREEN_WIDTH;
+    let y = y as usize % SCREEN_HEIGHT;
+
+    // Rows clipped at the bottom are never read
+    let rows = if cpu.quirks.wrap {
+        height as usize
+    } else {
+        (height as usize).min(SCREEN_HEIGHT - y)
+    };
+    let sprite = index_range(cpu, rows)?;
 
     let mut flipped = false;
     // Iterate over each row of our sprite
-    for y_line in 0..height {
+    for y_line in 0..rows {
         // Determine which memory address our row's data is stored
-        let addr = cpu.index_register + y_line as u16;
-        let pixels = cpu.game_memory[addr as usize];
+        let addr = sprite.start + y_line;
+        let pixels = cpu.game_memory[addr];
         // Iterate over each column in our row
         for x_line in 0..8 {
+            if !cpu.quirks.wrap && x + x_line >= SCREEN_WIDTH {
+                break;
+            }
+
             // Use a mask to fetch current pixel's bit. Only flip if a 1
             if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                // Sprites should wrap around screen, so apply modulo
-                let x = (x + x_line) as usize % SCREEN_WIDTH;
-                let y = (y + y_line) as usize % SCREEN_HEIGHT;
+                let x = (x + x_line) % SCREEN_WIDTH;
+                let y = (y + y_line) % SCREEN_HEIGHT;
 
                 // Get our pixel's index in the 1D screen array
                 let idx = x + SCREEN_WIDTH * y;
                 // Check if we're about to flip the pixel and set
                 flipped |= cpu.framebuffer[idx];
                 cpu.framebuffer[idx] ^= true;
+                cpu.dirty.mark_pixel(x, y);
             }
         }
     }
@@ -271,13 +306,20 @@ pub fn opcode_d_xyn(cpu: &mut CPU, opcode: u16) {
     } else {
         cpu.registers[0xF] = 0;
     }
+
+    // The VIP waited for the display interrupt before drawing
+    if cpu.quirks.vblank {
+        cpu.vblank_wait = true;
+    }
+
+    Ok(())
 }
 
 pub fn opcode_e_x9e(cpu: &mut CPU, opcode: u16) {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
-    if cpu.key[cpu.registers[regx as usize] as usize] == 1 {
+    if cpu.keypad.is_down(cpu.registers[regx as usize]) {
         cpu.program_counter += 4;
     } else {
         cpu.program_counter += 2;
@@ -288,13 +330,23 @@ pub fn opcode_e_xa1(cpu: &mut CPU, opcode: u16) {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
-    if cpu.key[cpu.registers[regx as usize] as usize] == 0 {
+    if !cpu.keypad.is_down(cpu.registers[regx as usize]) {
         cpu.program_counter += 4;
     } else {
         cpu.program_counter += 2;
     }
 }
 
+// XO-CHIP: the 16 bytes at I become the sound pattern
+pub fn opcode_f_002(cpu: &mut CPU) -> Result<(), String> {
+    let range = index_range(cpu, 16)?;
+    let mut pattern = [0; 16];
+    pattern.copy_from_slice(&cpu.game_memory[range]);
+
+    cpu.audio_pattern = Some(pattern);
+    Ok(())
+}
+
 pub fn opcode_f_x07(cpu: &mut CPU, opcode: u16) {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
@@ -306,18 +358,34 @@ pub fn opcode_f_x0a(cpu: &mut CPU, opcode: u16) {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
-    let mut key_pressed: bool = false;
-
-    for i in 0..cpu.key.len() {
-        if cpu.key[i] != 0 {
-            cpu.registers[regx as usize] = i as u8;
-            key_pressed = true;
+    // The program counter stays on this instruction until the wait is over
+    match cpu.key_wait {
+        KeyWait::Idle => {
+            // Only keys that go down from now on count
+            cpu.keypad.clear_edges();
+            cpu.key_wait = KeyWait::Waiting;
+        }
+        KeyWait::Waiting => {
+            if let Some(key) = cpu.keypad.first_pressed() {
+                cpu.keypad.clear_edges();
+
+                if cpu.quirks.key_wait_release {
+                    cpu.key_wait = KeyWait::Pressed(key);
+                } else {
+                    cpu.registers[regx as usize] = key;
+                    cpu.key_wait = KeyWait::Idle;
+                    cpu.program_counter += 2;
+                }
+            }
+        }
+        KeyWait::Pressed(key) => {
+            if cpu.keypad.was_released(key) {
+                cpu.registers[regx as usize] = key;
+                cpu.key_wait = KeyWait::Idle;
+                cpu.program_counter += 2;
+            }
         }
     }
-
-    if key_pressed {
-        cpu.program_counter += 2
-    };
 }
 
 pub fn opcode_f_x15(cpu: &mut CPU, opcode: u16) {
@@ -338,17 +406,22 @@ pub fn opcode_f_x1e(cpu: &mut CPU, opcode: u16) {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
-    cpu.index_register += cpu.registers[regx as usize] as u16 % 0xFFF;
+    // I only has room for the 12 bit address space
+    cpu.index_register = cpu
+        .index_register
+        .wrapping_add(cpu.registers[regx as usize] as u16)
+        & 0x0FFF;
 }
 
 pub fn opcode_f_x29(cpu: &mut CPU, opcode: u16) {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
-    cpu.index_register = cpu.registers[regx as usize] as u16 % 0x5;
+    // The font is at the start of memory, five bytes per digit
+    cpu.index_register = (cpu.registers[regx as usize] & 0xF) as u16 * 5;
 }
 
-pub fn opcode_f_x33(cpu: &mut CPU, opcode: u16) {
+pub fn opcode_f_x33(cpu: &mut CPU, opcode: u16) -> Result<(), String> {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
@@ -358,27 +431,65 @@ pub fn opcode_f_x33(cpu: &mut CPU, opcode: u16) {
     let tens = (value / 10) % 10;
     let units = value % 10;
 
-    cpu.game_memory[(cpu.index_register) as usize] = hundreds;
-    cpu.game_memory[(cpu.index_register + 1) as usize] = tens;
-    cpu.game_memory[(cpu.index_register + 2) as usize] = units;
+    let range = index_range(cpu, 3)?;
+    cpu.game_memory[range].copy_from_slice(&[hundreds, tens, units]);
+    Ok(())
 }
 
-pub fn opcode_f_x55(cpu: &mut CPU, opcode: u16) {
+pub fn opcode_f_x55(cpu: &mut CPU, opcode: u16) -> Result<(), String> {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
-    for i in 0..regx {
-        cpu.index_register += 1;
-        cpu.game_memory[cpu.index_register as usize] = cpu.registers[i as usize];
-    }
+    let range = index_range(cpu, regx as usize + 1)?;
+    cpu.game_memory[range].copy_from_slice(&cpu.registers[..=regx as usize]);
+
+    advance_index(cpu, regx);
+    Ok(())
 }
 
-pub fn opcode_f_x65(cpu: &mut CPU, opcode: u16) {
+pub fn opcode_f_x65(cpu: &mut CPU, opcode: u16) -> Result<(), String> {
     let mut regx = opcode & 0x0F00;
     regx >>= 8;
 
-    for i in 0..regx {
-        cpu.index_register += 1;
-        cpu.registers[i as usize] = cpu.game_memory[cpu.index_register as usize];
+    let range = index_range(cpu, regx as usize + 1)?;
+    cpu.registers[..=regx as usize].copy_from_slice(&cpu.game_memory[range]);
+
+    advance_index(cpu, regx);
+    Ok(())
+}
+
+// XO-CHIP: the rate the sound pattern plays at, see audio::Pattern
+pub fn opcode_f_x3a(cpu: &mut CPU, opcode: u16) {
+    let mut regx = opcode & 0x0F00;
+    regx >>= 8;
+
+    cpu.pitch = cpu.registers[regx as usize];
+}
+
+// The memory an instruction reads or writes from I on, an error when it runs
+// past the end instead of wrapping around.
+fn index_range(cpu: &CPU, length: usize) -> Result<Range<usize>, String> {
+    let start = cpu.index_register as usize;
+
+    if start + length > MEMORY_SIZE {
+        return Err(format!(
+            "{} bytes at I={:03X} run past the end of memory",
+            length, start
+        ));
+    }
+
+    Ok(start..start + length)
+}
+
+// Where I ends up after FX55 and FX65, depending on the memory quirks.
+fn advance_index(cpu: &mut CPU, regx: u16) {
+    if cpu.quirks.memory_leave_i_unchanged {
+        return;
     }
+
+    cpu.index_register += if cpu.quirks.memory_increment_by_x {
+        regx
+    } else {
+        regx + 1
+    };
 }
@@ -0,0 +1,63 @@
+use crate::{cpu::Framebuffer, dirty::DirtyRegion};
+
+pub const FULL_BRIGHTNESS: u8 = 255;
+
+// Phosphor persistence filter.
+//
+// opcode_d_xyn draws with XOR, so games erase a sprite and redraw it one
+// position over on every move. Shown raw that makes the sprite blink. Instead
+// of switching a pixel off at once we keep a brightness value per pixel and let
+// it fade out over a few frames, like the phosphor of an old CRT.
+#[derive(Debug, Clone)]
+pub struct Phosphor {
+    brightness: Vec<u8>,
+    // Frames each erased pixel has left to glow
+    frames_left: Vec<u8>,
+    fade_frames: u8,
+}
+
+impl Phosphor {
+    // fade_frames is how many frames an erased pixel keeps glowing.
+    // 0 turns the filter off and brightness just mirrors the framebuffer.
+    pub fn new(fade_frames: u8) -> Self {
+        Self {
+            brightness: Vec::new(),
+            frames_left: Vec::new(),
+            fade_frames,
+        }
+    }
+
+    // Folds the current framebuffer into the brightness buffer. Should be
+    // called once per presented frame. Pixels that are still fading out are
+    // added to `dirty` since they change without the core touching them.
+    pub fn update(&mut self, framebuffer: &Framebuffer, width: usize, dirty: &mut DirtyRegion) -> &[u8] {
+        if self.brightness.len() != framebuffer.len() {
+            self.brightness = vec![0; framebuffer.len()];
+            self.frames_left = vec![0; framebuffer.len()];
+        }
+
+        let fade = self.fade_frames as u32;
+
+        let pixels = self.brightness.iter_mut().zip(&mut self.frames_left).zip(framebuffer.iter());
+        for (idx, ((level, left), &lit)) in pixels.enumerate() {
+            // An erased pixel steps down evenly from full brightness and
+            // reaches 0 on the frame after the last one it glows on. The
+            // division rounds up, so no glowing frame comes out dark.
+            let new_level = if lit {
+                *left = self.fade_frames;
+                FULL_BRIGHTNESS
+            } else {
+                let glow = (FULL_BRIGHTNESS as u32 * *left as u32).div_ceil(fade + 1) as u8;
+                *left = left.saturating_sub(1);
+                glow
+            };
+
+            if new_level != *level {
+                dirty.mark_pixel(idx % width, idx / width);
+                *level = new_level;
+            }
+        }
+
+        &self.brightness
+    }
+}
@@ -0,0 +1,148 @@
+use std::{fmt, str::FromStr};
+
+// Behaviours that differ between CHIP-8 interpreters. ROMs written for one
+// interpreter can misbehave on another, so these are picked per ROM. The
+// names follow the quirks of the community chip-8-database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    // FX0A waits for the key to be released again, like the COSMAC VIP.
+    // When off it continues as soon as a key goes down.
+    pub key_wait_release: bool,
+    // 8XY6 and 8XYE shift VX in place instead of copying VY shifted into VX.
+    pub shift: bool,
+    // FX55 and FX65 leave I at I + X instead of I + X + 1.
+    pub memory_increment_by_x: bool,
+    // FX55 and FX65 leave I unchanged. Wins over memory_increment_by_x.
+    pub memory_leave_i_unchanged: bool,
+    // Sprites wrap around the screen edges instead of being clipped.
+    pub wrap: bool,
+    // BNNN jumps to XNN + VX instead of NNN + V0.
+    pub jump: bool,
+    // DXYN waits for the next frame, so at most one sprite is drawn per frame.
+    pub vblank: bool,
+    // 8XY1, 8XY2 and 8XY3 reset VF to 0.
+    pub logic: bool,
+}
+
+// Profile used when nothing else is known about a ROM
+pub const DEFAULT_PROFILE: &str = "modern";
+
+// Every name Quirks::profile knows
+pub const PROFILES: [&str; 4] = ["chip8", "modern", "schip", "xochip"];
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::profile(DEFAULT_PROFILE).unwrap()
+    }
+}
+
+impl Quirks {
+    // chip8 is the original COSMAC VIP interpreter, modern what most current
+    // emulators do, schip SUPER-CHIP 1.1 and xochip Octo's XO-CHIP.
+    pub fn profile(name: &str) -> Option<Self> {
+        let modern = Self {
+            key_wait_release: true,
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: false,
+            wrap: false,
+            jump: false,
+            vblank: false,
+            logic: false,
+        };
+
+        match name {
+            "chip8" => Some(Self {
+                vblank: true,
+                logic: true,
+                ..modern
+            }),
+            "modern" => Some(modern),
+            "schip" => Some(Self {
+                shift: true,
+                memory_leave_i_unchanged: true,
+                jump: true,
+                ..modern
+            }),
+            "xochip" => Some(Self {
+                wrap: true,
+                ..modern
+            }),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        match name {
+            "key_wait_release" => self.key_wait_release = value,
+            "shift" => self.shift = value,
+            "memory_increment_by_x" => self.memory_increment_by_x = value,
+            "memory_leave_i_unchanged" => self.memory_leave_i_unchanged = value,
+            "wrap" => self.wrap = value,
+            "jump" => self.jump = value,
+            "vblank" => self.vblank = value,
+            "logic" => self.logic = value,
+            _ => return Err(format!("unknown quirk '{}'", name)),
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    // Parses a profile name followed by optional overrides, separated by
+    // commas: "chip8" or "schip,wrap=on". When the profile is left out the
+    // quirks start from the default profile.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = s.split(',').map(str::trim).filter(|entry| !entry.is_empty()).peekable();
+
+        let mut quirks = match entries.peek() {
+            Some(first) if !first.contains('=') => {
+                let quirks = Quirks::profile(first)
+                    .ok_or_else(|| format!("unknown quirks profile '{}'", first))?;
+                entries.next();
+                quirks
+            }
+            _ => Quirks::default(),
+        };
+
+        for entry in entries {
+            let (name, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("invalid quirk '{}'", entry))?;
+
+            let value = match value.trim() {
+                "on" | "true" => true,
+                "off" | "false" => false,
+                _ => return Err(format!("invalid value in '{}', use on or off", entry)),
+            };
+
+            quirks.set(name.trim(), value)?;
+        }
+
+        Ok(quirks)
+    }
+}
+
+// Writes every quirk explicitly, in the format FromStr reads back.
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let on_off = |value: bool| if value { "on" } else { "off" };
+
+        write!(
+            f,
+            "key_wait_release={},shift={},memory_increment_by_x={},memory_leave_i_unchanged={},\
+             wrap={},jump={},vblank={},logic={}",
+            on_off(self.key_wait_release),
+            on_off(self.shift),
+            on_off(self.memory_increment_by_x),
+            on_off(self.memory_leave_i_unchanged),
+            on_off(self.wrap),
+            on_off(self.jump),
+            on_off(self.vblank),
+            on_off(self.logic)
+        )
+    }
+}
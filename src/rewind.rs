@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+
+// Rewind buffer. Save states are taken every few frames and kept for a fixed
+// amount of emulated time, oldest ones are dropped first.
+//
+// Only the newest state is stored whole. Every older one is kept as the
+// difference with the state after it: the two are XORed, which leaves zeros
+// everywhere except for the bytes that changed, and the zero runs are skipped.
+// Between two snapshots a game usually touches a handful of registers, a few
+// bytes of RAM and some of the framebuffer, so a delta is tiny next to a
+// full state.
+
+// Frames between two snapshots
+pub const REWIND_INTERVAL: u64 = 2;
+// Longest history the settings accept, an hour
+pub const MAX_REWIND_SECONDS: u64 = 3600;
+
+pub struct Rewind {
+    latest: Option<Vec<u8>>,
+    // Backward deltas, the last one turns `latest` into the state before it
+    deltas: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(seconds: u64) -> Self {
+        let snapshots = seconds.saturating_mul(60) / REWIND_INTERVAL;
+
+        Self {
+            latest: None,
+            deltas: VecDeque::new(),
+            capacity: usize::try_from(snapshots).unwrap_or(usize::MAX),
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some(latest) = self.latest.take() {
+            self.deltas.push_back(diff(&state, &latest));
+
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+
+        self.latest = Some(state);
+    }
+
+    // Drops the newest snapshot and returns the one before it, or None when
+    // the buffer has nothing older left.
+    pub fn step_back(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        let latest = self.latest.as_mut()?;
+
+        apply(latest, &delta);
+
+        Some(latest)
+    }
+}
+
+// Encodes what turns `from` into `to` as runs of [skip: u16][len: u16][XORed
+// bytes]. Both states have the same size, save states have a fixed layout.
+fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < from.len() {
+        let start = pos;
+
+        while pos < from.len() && pos - start < u16::MAX as usize && from[pos] == to[pos] {
+            pos += 1;
+        }
+
+        let skip = pos - start;
+        let run_start = pos;
+
+        while pos < from.len() && pos - run_start < u16::MAX as usize && from[pos] != to[pos] {
+            pos += 1;
+        }
+
+        if pos == run_start && pos == from.len() {
+            break;
+        }
+
+        out.extend_from_slice(&(skip as u16).to_le_bytes());
+        out.extend_from_slice(&((pos - run_start) as u16).to_le_bytes());
+        out.extend(from[run_start..pos].iter().zip(&to[run_start..pos]).map(|(a, b)| a ^ b));
+    }
+
+    out
+}
+
+fn apply(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut runs = delta;
+
+    while runs.len() >= 4 {
+        let skip = u16::from_le_bytes([runs[0], runs[1]]) as usize;
+        let len = u16::from_le_bytes([runs[2], runs[3]]) as usize;
+
+        pos += skip;
+
+        for (byte, change) in state[pos..pos + len].iter_mut().zip(&runs[4..4 + len]) {
+            *byte ^= change;
+        }
+
+        pos += len;
+        runs = &runs[4 + len..];
+    }
+}
@@ -0,0 +1,131 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+// Emulation speed. Everything inside the CPU, the timers included, counts in
+// emulated 60 Hz frames; the speed only changes how much wall time the main
+// loop waits between two of them. That way a game runs the same at any speed,
+// it just gets there sooner or later.
+
+const FRAME_DURATION: Duration = Duration::from_micros(1_000_000 / 60);
+
+// Steps the faster and slower hotkeys go through, before unlimited
+const MULTIPLIERS: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    // Frames per 60 Hz tick of wall time
+    Multiplier(f32),
+    // As fast as the host can go
+    Unlimited,
+}
+
+impl FromStr for Speed {
+    type Err = String;
+
+    // "unlimited", or a multiplier of normal speed like "0.25", "2" or "4x".
+    // Multipliers stay within the range the hotkeys step through.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "unlimited" {
+            return Ok(Speed::Unlimited);
+        }
+
+        let (min, max) = (MULTIPLIERS[0], MULTIPLIERS[MULTIPLIERS.len() - 1]);
+
+        s.strip_suffix('x')
+            .unwrap_or(s)
+            .parse::<f32>()
+            .ok()
+            .filter(|multiplier| (min..=max).contains(multiplier))
+            .map(Speed::Multiplier)
+            .ok_or_else(|| {
+                format!(
+                    "invalid speed '{}', expected a multiplier from {} to {} or unlimited",
+                    s, min, max
+                )
+            })
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Speed::Multiplier(multiplier) => write!(f, "{}x", multiplier),
+            Speed::Unlimited => write!(f, "unlimited"),
+        }
+    }
+}
+
+// Decides when the main loop runs the next frame: at which speed, whether
+// it is paused and whether a single step was asked for while paused.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeedControl {
+    pub speed: Speed,
+    pub paused: bool,
+    step: bool,
+}
+
+impl SpeedControl {
+    pub fn new(speed: Speed) -> Self {
+        Self {
+            speed,
+            paused: false,
+            step: false,
+        }
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = match self.speed {
+            Speed::Multiplier(current) => MULTIPLIERS
+                .iter()
+                .find(|&&multiplier| multiplier > current)
+                .map_or(Speed::Unlimited, |&multiplier| Speed::Multiplier(multiplier)),
+            Speed::Unlimited => Speed::Unlimited,
+        };
+    }
+
+    pub fn slower(&mut self) {
+        let current = match self.speed {
+            Speed::Multiplier(current) => current,
+            Speed::Unlimited => f32::INFINITY,
+        };
+
+        self.speed = MULTIPLIERS
+            .iter()
+            .rev()
+            .find(|&&multiplier| multiplier < current)
+            .map_or(Speed::Multiplier(MULTIPLIERS[0]), |&multiplier| {
+                Speed::Multiplier(multiplier)
+            });
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.step = false;
+    }
+
+    // Lets exactly one frame through while paused.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.step = true;
+        }
+    }
+
+    // Whether the next frame should be emulated, uses up a pending step.
+    pub fn take_frame(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+
+        std::mem::take(&mut self.step)
+    }
+
+    // Wall time one pass of the main loop should take, None when it should
+    // not wait at all. While paused the loop keeps going at 60 Hz to stay
+    // responsive.
+    pub fn frame_duration(&self) -> Option<Duration> {
+        match self.speed {
+            _ if self.paused => Some(FRAME_DURATION),
+            Speed::Multiplier(multiplier) => Some(FRAME_DURATION.div_f32(multiplier)),
+            Speed::Unlimited => None,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    cpu::{KeyWait, CPU, MEMORY_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH},
+    input::Keypad,
+};
+
+// Save states. Everything that changes while a ROM runs is written into a
+// flat byte buffer; quirks and other settings are left out since they come
+// from the configuration.
+
+const MAGIC: &[u8; 3] = b"C8S";
+// 3 adds the XO-CHIP sound pattern and pitch. 2 has a full 4 KiB of
+// memory, 1 was a byte short.
+const VERSION: u8 = 3;
+
+impl CPU {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.game_memory.len() + 512);
+
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+
+        out.extend_from_slice(&self.game_memory);
+        out.extend_from_slice(&self.registers);
+        push_u16(&mut out, self.index_register);
+        push_u16(&mut out, self.program_counter);
+        for value in self.stack {
+            push_u16(&mut out, value);
+        }
+        push_u16(&mut out, self.stack_pointer);
+        push_u16(&mut out, self.cur_opcode);
+
+        // Eight pixels per byte
+        for pixels in self.framebuffer.chunks(8) {
+            out.push(pixels.iter().fold(0, |byte, &lit| byte << 1 | lit as u8));
+        }
+
+        for bits in self.keypad.to_bits() {
+            push_u16(&mut out, bits);
+        }
+        match self.key_wait {
+            KeyWait::Idle => out.extend_from_slice(&[0, 0]),
+            KeyWait::Waiting => out.extend_from_slice(&[1, 0]),
+            KeyWait::Pressed(key) => out.extend_from_slice(&[2, key]),
+        }
+
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.push(self.audio_pattern.is_some() as u8);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or_default());
+        out.push(self.pitch);
+        out.extend_from_slice(&self.frame_count.to_le_bytes());
+        out.extend_from_slice(&self.rng.state.to_le_bytes());
+
+        out
+    }
+
+    // Restores a state written by save_state. On error the CPU is left as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err("not a save state".to_owned());
+        }
+        if reader.u8()? != VERSION {
+            return Err("save state from an unsupported version".to_owned());
+        }
+
+        let mut game_memory = [0; MEMORY_SIZE];
+        game_memory.copy_from_slice(reader.bytes(MEMORY_SIZE)?);
+        let mut registers = [0; 16];
+        registers.copy_from_slice(reader.bytes(16)?);
+        let index_register = reader.u16()?;
+        let program_counter = reader.u16()?;
+        let mut stack = [0; 16];
+        for value in stack.iter_mut() {
+            *value = reader.u16()?;
+        }
+        let stack_pointer = reader.u16()?;
+        let cur_opcode = reader.u16()?;
+
+        let mut framebuffer = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for (pixels, &byte) in framebuffer
+            .chunks_mut(8)
+            .zip(reader.bytes(SCREEN_WIDTH * SCREEN_HEIGHT / 8)?)
+        {
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = byte & (0x80 >> i) != 0;
+            }
+        }
+
+        let keypad = Keypad::from_bits([reader.u16()?, reader.u16()?, reader.u16()?]);
+        let key_wait = match (reader.u8()?, reader.u8()?) {
+            (0, _) => KeyWait::Idle,
+            (1, _) => KeyWait::Waiting,
+            (2, key) => KeyWait::Pressed(key & 0xF),
+            _ => return Err("corrupted save state".to_owned()),
+        };
+
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let has_pattern = reader.u8()?;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(reader.bytes(16)?);
+        let audio_pattern = match has_pattern {
+            0 => None,
+            1 => Some(pattern),
+            _ => return Err("corrupted save state".to_owned()),
+        };
+        let pitch = reader.u8()?;
+        let frame_count = reader.u64()?;
+        let rng_state = reader.u64()?;
+
+        if reader.pos != data.len() || stack_pointer as usize > stack.len() {
+            return Err("corrupted save state".to_owned());
+        }
+
+        self.game_memory = game_memory;
+        self.registers = registers;
+        self.index_register = index_register;
+        self.program_counter = program_counter;
+        self.stack = stack;
+        self.stack_pointer = stack_pointer;
+        self.cur_opcode = cur_opcode;
+        self.framebuffer = framebuffer;
+        self.keypad = keypad;
+        self.key_wait = key_wait;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.frame_count = frame_count;
+        self.rng.state = rng_state;
+
+        self.dirty.mark_all(SCREEN_WIDTH, SCREEN_HEIGHT);
+
+        Ok(())
+    }
+}
+
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or("save state is cut short")?;
+
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
@@ -0,0 +1,100 @@
+use std::{fs, path::PathBuf};
+
+use chip_8_emulator::config::Config;
+
+// Settings files with values out of range, which have to be turned down with
+// the file and line like unknown keys are.
+
+fn load(name: &str, text: &str) -> Result<Config, String> {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.toml", name));
+    fs::write(&path, text).unwrap();
+
+    Config::load(Some(path))
+}
+
+#[test]
+fn values_in_range_load() {
+    let text = "scale = 64\naudio = \"null\"\nvolume = 1.0\nfrequency = 880.0\n\
+                [rom.abc]\nscale = 1\nvolume = 0.0\n";
+
+    let settings = load("in-range", text).unwrap().for_rom("abc");
+
+    assert_eq!(settings.scale, Some(1));
+    assert_eq!(settings.audio.as_deref(), Some("null"));
+    assert_eq!(settings.volume, Some(0.0));
+    assert_eq!(settings.frequency, Some(880.0));
+}
+
+#[test]
+fn values_out_of_range_point_at_their_line() {
+    // File name, contents, line of the value and what the error is about
+    let table = [
+        ("unknown-key", "scale = 2\nbogus = 1\n", 2, "unknown field"),
+        (
+            "scale-zero",
+            "scale = 0\n",
+            1,
+            "scale must be between 1 and 64",
+        ),
+        (
+            "scale-large",
+            "[rom.abc]\nscale = 65\n",
+            2,
+            "scale must be between 1 and 64",
+        ),
+        (
+            "filters-scale",
+            "filters = \"scale=100000,bloom\"\n",
+            1,
+            "scale must be between 1 and 64",
+        ),
+        (
+            "keymap-hotkey",
+            "keymap = \"azerty,F5=A\"\n",
+            1,
+            "'F5' is an emulator control and cannot be bound",
+        ),
+        (
+            "audio",
+            "palette = \"amber\"\naudio = \"pulse\"\n",
+            2,
+            "unknown audio output 'pulse'",
+        ),
+        (
+            "volume",
+            "volume = 1.5\n",
+            1,
+            "volume must be between 0.0 and 1.0",
+        ),
+        (
+            "rewind",
+            "rewind_seconds = 3601\n",
+            1,
+            "rewind_seconds must be between 0 and 3600",
+        ),
+        (
+            "frequency",
+            "frequency = 0.0\n",
+            1,
+            "frequency must be above 0",
+        ),
+    ];
+
+    for (name, text, line, message) in table {
+        let error = load(name, text).unwrap_err();
+
+        assert!(
+            error.contains(&format!("{}.toml", name)),
+            "{}: {}",
+            name,
+            error
+        );
+        assert!(
+            error.contains(&format!("line {}", line)),
+            "{}: {}",
+            name,
+            error
+        );
+        assert!(error.contains(message), "{}: {}", name, error);
+    }
+}
@@ -0,0 +1,203 @@
+use std::path::Path;
+
+use chip_8_emulator::{
+    conformance::{self, Marks, Run, Verdict, SUITE},
+    cpu::{Framebuffer, SCREEN_WIDTH},
+    quirks::{Quirks, PROFILES},
+};
+
+// The scoring of the conformance runner. ROMS/TEST.ch8 is corax89's opcode
+// test, the original of the suite's corax+, and stands in for the suite.
+
+fn rom() -> Vec<u8> {
+    std::fs::read(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("ROMS")
+            .join("TEST.ch8"),
+    )
+    .unwrap()
+}
+
+fn test(name: &str) -> &'static conformance::Test {
+    SUITE.iter().find(|test| test.name == name).unwrap()
+}
+
+// A blank screen with 3x3 glyphs drawn at the given positions
+fn screen(glyphs: &[([u8; 3], usize, usize)]) -> Framebuffer {
+    let mut framebuffer = [false; SCREEN_WIDTH * 32];
+
+    for &(rows, x, y) in glyphs {
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..3 {
+                framebuffer[(y + row) * SCREEN_WIDTH + x + column] = bits & (0b100 >> column) != 0;
+            }
+        }
+    }
+
+    framebuffer
+}
+
+fn finished(framebuffer: Framebuffer) -> Run {
+    Run {
+        framebuffer,
+        sound: false,
+        error: None,
+    }
+}
+
+const TICK: [u8; 3] = [0b101, 0b110, 0b100];
+const CROSS: [u8; 3] = [0b101, 0b010, 0b101];
+
+#[test]
+fn identifies_suite_roms_by_file_name() {
+    // File name, test
+    let table = [
+        ("2-ibm-logo.ch8", Some("ibm")),
+        ("IBM Logo.ch8", Some("ibm")),
+        ("3-corax+.ch8", Some("corax")),
+        ("4-flags.ch8", Some("flags")),
+        ("5-quirks.ch8", Some("quirks")),
+        ("6-keypad.ch8", Some("keypad")),
+        ("7-beep.ch8", Some("beep")),
+        ("INVADERS.ch8", None),
+    ];
+
+    for (file_name, expected) in table {
+        let test = conformance::identify(Path::new("roms").join(file_name).as_path());
+        assert_eq!(test.map(|test| test.name), expected, "{}", file_name);
+    }
+}
+
+#[test]
+fn quirks_modes_only_run_under_their_profile() {
+    for mode in test("quirks").modes {
+        let profiles: Vec<&str> = PROFILES
+            .iter()
+            .copied()
+            .filter(|profile| mode.runs_under(profile))
+            .collect();
+
+        assert_eq!(profiles, [mode.name], "{}", mode.name);
+    }
+
+    for mode in test("keypad").modes {
+        assert!(PROFILES.iter().all(|profile| mode.runs_under(profile)), "{}", mode.name);
+    }
+}
+
+#[test]
+fn reads_every_mark_of_the_opcode_test() {
+    let test = test("corax");
+    let mode = &test.modes[0];
+
+    for profile in PROFILES {
+        let run = conformance::run(test, mode, &rom(), Quirks::profile(profile).unwrap()).unwrap();
+        let marks = conformance::read_marks(&run.framebuffer);
+
+        // Four columns of six rows, the last two rows are one mark short
+        assert_eq!(
+            (marks.ticks.len(), marks.crosses.len()),
+            (22, 0),
+            "{}",
+            profile
+        );
+        assert_eq!(marks.ticks[0], (11, 2), "{}", profile);
+        assert_eq!(
+            conformance::score(test, &run, None),
+            Verdict::Pass,
+            "{}",
+            profile
+        );
+    }
+}
+
+#[test]
+fn crosses_fail_the_check() {
+    let test = test("flags");
+    // The X of a label is a row taller than a cross
+    let label = [0b101, 0b010, 0b101];
+    let mut framebuffer = screen(&[(TICK, 4, 2), (CROSS, 20, 10), (label, 40, 10)]);
+    framebuffer[13 * SCREEN_WIDTH + 40] = true;
+
+    assert_eq!(
+        conformance::read_marks(&framebuffer),
+        Marks {
+            ticks: vec![(4, 2)],
+            crosses: vec![(20, 10)],
+        }
+    );
+    assert_eq!(
+        conformance::score(test, &finished(framebuffer), None),
+        Verdict::Fail("1 of 2 checks show a cross, at 20,10".to_owned())
+    );
+}
+
+#[test]
+fn a_screen_without_marks_fails() {
+    let test = test("quirks");
+
+    assert_eq!(
+        conformance::score(test, &finished(screen(&[])), None),
+        Verdict::Fail("no ticks or crosses on the screen".to_owned())
+    );
+}
+
+#[test]
+fn scores_against_the_expected_frame() {
+    let test = test("ibm");
+    let mode = &test.modes[0];
+    let run = conformance::run(test, mode, &rom(), Quirks::default()).unwrap();
+
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("conformance")
+        .join(format!(
+            "{}.png",
+            conformance::check_name(test, mode, "modern")
+        ));
+    conformance::save_frame(&path, &run.framebuffer).unwrap();
+    let mut expected = conformance::load_frame(&path).unwrap().unwrap();
+
+    assert_eq!(
+        conformance::score(test, &run, Some(&expected)),
+        Verdict::Pass
+    );
+    assert_eq!(
+        conformance::score(test, &run, None),
+        Verdict::Fail("no expected frame".to_owned())
+    );
+
+    expected[0] = !expected[0];
+    assert_eq!(
+        conformance::score(test, &run, Some(&expected)),
+        Verdict::Fail("1 pixel differs".to_owned())
+    );
+
+    let missing = path.with_file_name("missing.png");
+    assert_eq!(conformance::load_frame(&missing).unwrap(), None);
+}
+
+#[test]
+fn beep_needs_sound() {
+    let test = test("beep");
+    let mode = &test.modes[0];
+    // Never starts the sound timer
+    let run = conformance::run(test, mode, &[0x12, 0x00], Quirks::default()).unwrap();
+
+    assert_eq!(
+        conformance::score(test, &run, None),
+        Verdict::Fail("never beeped".to_owned())
+    );
+}
+
+#[test]
+fn errors_fail_the_check() {
+    let test = test("corax");
+    let mode = &test.modes[0];
+    // 00EE with nothing on the stack
+    let run = conformance::run(test, mode, &[0x00, 0xEE], Quirks::default()).unwrap();
+
+    match conformance::score(test, &run, None) {
+        Verdict::Fail(reason) => assert!(reason.contains("empty stack"), "{}", reason),
+        verdict => panic!("{:?}", verdict),
+    }
+}
@@ -0,0 +1,272 @@
+mod reference;
+
+use std::fmt::Write;
+
+use chip_8_emulator::{
+    cpu::{CPU, SCREEN_HEIGHT, SCREEN_WIDTH},
+    instruction::Instruction,
+    quirks::{Quirks, PROFILES},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use reference::{Machine, HEIGHT, WIDTH};
+
+// Runs random programs through CPU and the reference machine side by side,
+// once for each quirks profile, and compares everything a program can see
+// after every instruction. A divergence is reported with the smallest
+// program that still shows it, found by leaving out instructions one at a
+// time while the two keep disagreeing.
+
+const PROGRAMS: u64 = 400;
+const MAX_LENGTH: usize = 24;
+const STEPS: usize = 96;
+// Instructions between timer ticks, like a frame
+const TICK_EVERY: usize = 8;
+const START: u16 = 0x200;
+
+// Everything a CHIP-8 program can observe
+#[derive(Debug, PartialEq)]
+struct State {
+    pc: u16,
+    i: u16,
+    v: [u8; 16],
+    // Return addresses, innermost last
+    stack: Vec<u16>,
+    delay: u8,
+    sound: u8,
+    memory: Vec<u8>,
+    screen: Vec<bool>,
+}
+
+impl State {
+    fn of_cpu(cpu: &CPU) -> Self {
+        State {
+            pc: cpu.program_counter,
+            i: cpu.index_register,
+            v: cpu.registers,
+            // CPU keeps the address of the call, not the one to return to
+            stack: cpu.stack[..cpu.stack_pointer as usize]
+                .iter()
+                .map(|call| call + 2)
+                .collect(),
+            delay: cpu.delay_timer,
+            sound: cpu.sound_timer,
+            memory: cpu.game_memory.to_vec(),
+            screen: cpu.framebuffer.to_vec(),
+        }
+    }
+
+    fn of_reference(machine: &Machine) -> Self {
+        State {
+            pc: machine.pc,
+            i: machine.i,
+            v: machine.v,
+            stack: machine.stack.clone(),
+            delay: machine.delay,
+            sound: machine.sound,
+            memory: machine.memory.clone(),
+            screen: machine.screen.iter().flatten().copied().collect(),
+        }
+    }
+
+    // What differs between the two, one line each
+    fn differences(&self, reference: &State) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        let mut compare = |name: &str, cpu: String, reference: String| {
+            if cpu != reference {
+                differences.push(format!("{}: cpu {} reference {}", name, cpu, reference));
+            }
+        };
+
+        compare("PC", format!("{:03X}", self.pc), format!("{:03X}", reference.pc));
+        compare("I", format!("{:03X}", self.i), format!("{:03X}", reference.i));
+        for (x, (a, b)) in self.v.iter().zip(&reference.v).enumerate() {
+            compare(&format!("V{:X}", x), format!("{:02X}", a), format!("{:02X}", b));
+        }
+        compare(
+            "stack",
+            format!("{:03X?}", self.stack),
+            format!("{:03X?}", reference.stack),
+        );
+        compare("DT", self.delay.to_string(), reference.delay.to_string());
+        compare("ST", self.sound.to_string(), reference.sound.to_string());
+        for (address, (a, b)) in self.memory.iter().zip(&reference.memory).enumerate() {
+            compare(
+                &format!("memory {:03X}", address),
+                format!("{:02X}", a),
+                format!("{:02X}", b),
+            );
+        }
+        for (pixel, (a, b)) in self.screen.iter().zip(&reference.screen).enumerate() {
+            compare(
+                &format!("pixel {},{}", pixel % WIDTH, pixel / WIDTH),
+                a.to_string(),
+                b.to_string(),
+            );
+        }
+
+        differences
+    }
+}
+
+struct Divergence {
+    // Instructions run, counting the one that diverged
+    step: usize,
+    differences: Vec<String>,
+}
+
+// Runs both until they disagree, None when they agree all the way or up to
+// where the reference reaches behaviour CHIP-8 leaves undefined. CPU may
+// return an error there, but nowhere else.
+fn run(program: &[u16], quirks: Quirks, seed: u64) -> Option<Divergence> {
+    let bytes: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    cpu.reseed(seed);
+    cpu.quirks = quirks;
+    cpu.game_memory[START as usize..START as usize + bytes.len()].copy_from_slice(&bytes);
+
+    let mut machine = Machine::new(&bytes, quirks, seed);
+
+    for step in 1..=STEPS {
+        if machine.step().is_err() {
+            return None;
+        }
+        if let Err(e) = cpu.update() {
+            let differences = vec![format!("cpu failed where the reference did not: {}", e)];
+            return Some(Divergence { step, differences });
+        }
+
+        if step % TICK_EVERY == 0 {
+            cpu.tick_timers();
+            machine.tick_timers();
+        }
+
+        let (actual, expected) = (State::of_cpu(&cpu), State::of_reference(&machine));
+        if actual != expected {
+            let differences = actual.differences(&expected);
+            return Some(Divergence { step, differences });
+        }
+    }
+
+    None
+}
+
+// Leaves out instructions for as long as the divergence stays. Removing one
+// moves the jump targets after it, so passes repeat until none can go.
+fn minimise(mut program: Vec<u16>, quirks: Quirks, seed: u64) -> Vec<u16> {
+    loop {
+        let length = program.len();
+        let mut at = 0;
+
+        while at < program.len() {
+            let mut shorter = program.clone();
+            shorter.remove(at);
+
+            if !shorter.is_empty() && run(&shorter, quirks, seed).is_some() {
+                program = shorter;
+            } else {
+                at += 1;
+            }
+        }
+
+        if program.len() == length {
+            return program;
+        }
+    }
+}
+
+// A random instruction that CHIP-8 defines. Addresses stay inside the
+// program, so jumps land on its instructions.
+fn random_instruction(rng: &mut StdRng, length: usize) -> u16 {
+    let x = rng.gen_range(0..16u16) << 8;
+    let y = rng.gen_range(0..16u16) << 4;
+    let nn = rng.gen_range(0..256u16);
+    let target = START + 2 * rng.gen_range(0..length as u16);
+
+    match rng.gen_range(0..34) {
+        0 => 0x00E0,
+        1 => 0x00EE,
+        2 => rng.gen_range(0x001..0x0E0),
+        3 => 0x1000 | target,
+        4 => 0x2000 | target,
+        5 => 0x3000 | x | nn,
+        6 => 0x4000 | x | nn,
+        7 => 0x5000 | x | y,
+        8..=10 => 0x6000 | x | nn,
+        11 => 0x7000 | x | nn,
+        12..=20 => 0x8000 | x | y | [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE][rng.gen_range(0..9)],
+        21 => 0x9000 | x | y,
+        22 => 0xA000 | rng.gen_range(0..0x1000),
+        23 => 0xB000 | target,
+        24 => 0xC000 | x | nn,
+        25 | 26 => 0xD000 | x | y | rng.gen_range(0..16),
+        27 => 0xE09E | x,
+        28 => 0xE0A1 | x,
+        29 => 0xF000 | x | [0x07, 0x0A, 0x15, 0x18][rng.gen_range(0..4)],
+        30 => 0xF01E | x,
+        31 => 0xF029 | x,
+        32 => 0xF033 | x,
+        _ => 0xF000 | x | [0x55, 0x65][rng.gen_range(0..2)],
+    }
+}
+
+fn random_program(seed: u64) -> Vec<u16> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let length = rng.gen_range(1..=MAX_LENGTH);
+
+    (0..length)
+        .map(|_| random_instruction(&mut rng, length))
+        .collect()
+}
+
+fn report(profile: &str, seed: u64, program: &[u16], divergence: &Divergence) -> String {
+    let mut report = format!(
+        "CPU and the reference disagree under the {} profile (program seed {}) \
+         after {} instructions of:\n",
+        profile, seed, divergence.step
+    );
+
+    for (at, opcode) in program.iter().enumerate() {
+        let _ = writeln!(
+            report,
+            "  {:03X}: {:04X}  {}",
+            START as usize + 2 * at,
+            opcode,
+            Instruction::decode(*opcode)
+        );
+    }
+
+    report.push_str("differences:\n");
+    for difference in divergence.differences.iter().take(16) {
+        let _ = writeln!(report, "  {}", difference);
+    }
+    if divergence.differences.len() > 16 {
+        let _ = writeln!(report, "  and {} more", divergence.differences.len() - 16);
+    }
+
+    report
+}
+
+#[test]
+fn screen_sizes_match() {
+    assert_eq!((WIDTH, HEIGHT), (SCREEN_WIDTH, SCREEN_HEIGHT));
+}
+
+#[test]
+fn random_programs_match_the_reference() {
+    for &profile in PROFILES.iter() {
+        let quirks = Quirks::profile(profile).unwrap();
+
+        for seed in 0..PROGRAMS {
+            let program = random_program(seed);
+
+            if run(&program, quirks, seed).is_some() {
+                let program = minimise(program, quirks, seed);
+                let divergence = run(&program, quirks, seed).unwrap();
+                panic!("{}", report(profile, seed, &program, &divergence));
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use chip_8_emulator::cpu::{Framebuffer, CPU, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Runs the bundled ROMs headless with scripted input and compares the final
+// framebuffer with a golden image in tests/golden. After an intended change
+// to what a ROM shows, write new goldens with:
+//
+//   BLESS=1 cargo test --test golden
+//
+// On a mismatch the actual and expected frames and a diff are saved next to
+// the other test output in target/tmp/golden, the diff shows pixels only lit
+// in the actual frame in green and pixels missing from it in red.
+
+const SEED: u64 = 0;
+
+struct Case {
+    name: &'static str,
+    rom: &'static str,
+    frames: u64,
+    // Frame, CHIP-8 key and whether it goes down or up, applied before the
+    // frame runs like the events of a movie
+    input: &'static [(u64, u8, bool)],
+    // Where the program has to stop on an instruction this core cannot run,
+    // for a ROM that shows nothing when it passes
+    stops_at: Option<u16>,
+}
+
+fn check(case: &Case) {
+    let actual = run(case);
+    let golden = golden_dir().join(format!("{}.png", case.name));
+
+    if env::var_os("BLESS").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        save(&golden, &frame_image(&actual));
+        return;
+    }
+
+    let expected = match load(&golden) {
+        Some(expected) => expected,
+        None => {
+            let output = failure_dir().join(format!("{}.actual.png", case.name));
+            save(&output, &frame_image(&actual));
+            panic!(
+                "{}: no golden image at {}, the actual frame is in {}. Run with BLESS=1 to add it",
+                case.name,
+                golden.display(),
+                output.display()
+            );
+        }
+    };
+
+    if actual[..] == expected[..] {
+        return;
+    }
+
+    let directory = failure_dir();
+    let different = actual.iter().zip(&expected).filter(|(a, e)| a != e).count();
+
+    save(&directory.join(format!("{}.actual.png", case.name)), &frame_image(&actual));
+    save(&directory.join(format!("{}.expected.png", case.name)), &frame_image(&expected));
+    save(&directory.join(format!("{}.diff.png", case.name)), &diff(&actual, &expected));
+
+    panic!(
+        "{}: {} pixels differ from {}, see {}",
+        case.name,
+        different,
+        golden.display(),
+        directory.join(format!("{}.diff.png", case.name)).display()
+    );
+}
+
+fn run(case: &Case) -> Framebuffer {
+    let rom = Path::new(env!("CARGO_MANIFEST_DIR")).join("ROMS").join(case.rom);
+
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    cpu.load_rom(&rom).unwrap();
+    cpu.reseed(SEED);
+
+    for frame in 0..case.frames {
+        for &(_, key, pressed) in case.input.iter().filter(|(at, ..)| *at == frame) {
+            if pressed {
+                cpu.key_down(key);
+            } else {
+                cpu.key_up(key);
+            }
+        }
+
+        // Only a ROM that is expected to stop may fail
+        if let Err(e) = cpu.run_frame() {
+            assert!(case.stops_at.is_some(), "{}: {}", case.name, e);
+            break;
+        }
+    }
+
+    if let Some(stops_at) = case.stops_at {
+        assert_eq!(
+            cpu.program_counter, stops_at,
+            "{}: stopped at {:03X} instead of {:03X}",
+            case.name, cpu.program_counter, stops_at
+        );
+    }
+
+    cpu.framebuffer
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn failure_dir() -> PathBuf {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn frame_image(framebuffer: &Framebuffer) -> Vec<[u8; 3]> {
+    framebuffer
+        .iter()
+        .map(|&lit| if lit { [0xFF; 3] } else { [0x00; 3] })
+        .collect()
+}
+
+fn diff(actual: &Framebuffer, expected: &Framebuffer) -> Vec<[u8; 3]> {
+    actual
+        .iter()
+        .zip(expected)
+        .map(|pixels| match pixels {
+            (true, true) => [0x60, 0x60, 0x60],
+            (true, false) => [0x00, 0xFF, 0x00],
+            (false, true) => [0xFF, 0x00, 0x00],
+            (false, false) => [0x00, 0x00, 0x00],
+        })
+        .collect()
+}
+
+fn save(path: &Path, pixels: &[[u8; 3]]) {
+    let file = File::create(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        SCREEN_WIDTH as u32,
+        SCREEN_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels.as_flattened()))
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+}
+
+// A golden written by `save`, None when there is none yet
+fn load(path: &Path) -> Option<Framebuffer> {
+    let file = File::open(path).ok()?;
+    let mut reader = png::Decoder::new(file)
+        .read_info()
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut pixels)
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+
+    assert_eq!(
+        (info.width as usize, info.height as usize, info.color_type),
+        (SCREEN_WIDTH, SCREEN_HEIGHT, png::ColorType::Rgb),
+        "{}: not a golden frame",
+        path.display()
+    );
+
+    let mut framebuffer = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+    for (lit, pixel) in framebuffer.iter_mut().zip(pixels.chunks_exact(3)) {
+        *lit = pixel[0] >= 0x80;
+    }
+
+    Some(framebuffer)
+}
+
+// SCTEST only draws when a check fails, or "OK" after the SUPER-CHIP
+// checks, which this CHIP-8 core cannot run. Passing every CHIP-8 check
+// leaves a blank screen and the program stopped on FX75 at 38C, the first
+// SUPER-CHIP instruction.
+#[test]
+fn sctest() {
+    check(&Case {
+        name: "sctest",
+        rom: "SCTEST.CH8",
+        frames: 300,
+        input: &[],
+        stops_at: Some(0x38C),
+    });
+}
+
+#[test]
+fn test_rom() {
+    check(&Case {
+        name: "test",
+        rom: "TEST.ch8",
+        frames: 300,
+        input: &[],
+        stops_at: None,
+    });
+}
+
+#[test]
+fn invaders_title() {
+    check(&Case {
+        name: "invaders_title",
+        rom: "INVADERS.ch8",
+        frames: 120,
+        input: &[],
+        stops_at: None,
+    });
+}
+
+#[test]
+fn invaders_start_and_move() {
+    check(&Case {
+        name: "invaders_start_and_move",
+        rom: "INVADERS.ch8",
+        frames: 450,
+        input: &[
+            (200, 0x5, true),
+            (210, 0x5, false),
+            (300, 0x4, true),
+            (330, 0x4, false),
+            (350, 0x5, true),
+            (354, 0x5, false),
+        ],
+        stops_at: None,
+    });
+}
+
+#[test]
+fn tetris_drop() {
+    check(&Case {
+        name: "tetris_drop",
+        rom: "TETRIS.ch8",
+        frames: 300,
+        input: &[
+            (60, 0x4, true),
+            (64, 0x4, false),
+            (80, 0x6, true),
+            (100, 0x6, false),
+            (120, 0x7, true),
+            (180, 0x7, false),
+        ],
+        stops_at: None,
+    });
+}
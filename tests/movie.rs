@@ -0,0 +1,77 @@
+use std::{fs, path::PathBuf};
+
+use chip_8_emulator::{movie::Movie, quirks::Quirks};
+
+fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.movie", name))
+}
+
+#[test]
+fn a_saved_movie_loads_back_the_same() {
+    let quirks: Quirks = "schip,wrap=on".parse().unwrap();
+    let mut movie = Movie::new(
+        "0123456789abcdef0123456789abcdef01234567".to_owned(),
+        quirks,
+        42,
+        30,
+    );
+    movie.record(120, 0x5, true);
+    movie.record(128, 0x5, false);
+    movie.record(128, 0xF, true);
+    movie.length = 600;
+
+    let path = path("round-trip");
+    movie.save(&path).unwrap();
+
+    assert_eq!(Movie::load(&path).unwrap(), movie);
+}
+
+#[test]
+fn version_1_movies_still_load() {
+    let path = path("version-1");
+    fs::write(&path, "chip8-movie 1\nrom abc\nseed 7\n10 down A\nend 20\n").unwrap();
+
+    let movie = Movie::load(&path).unwrap();
+
+    assert_eq!(movie.seed, 7);
+    assert_eq!(movie.instructions_per_frame, 10);
+    assert_eq!(movie.events.len(), 1);
+    assert_eq!(movie.length, 20);
+}
+
+#[test]
+fn bad_movies_are_turned_down() {
+    // File name, contents and what the error is about
+    let table = [
+        ("magic", "chip8-movie 3\nend 1\n", "not a movie file"),
+        (
+            "ipf-zero",
+            "chip8-movie 2\nipf 0\nend 1\n",
+            "line 2: ipf must be between 1 and 1000",
+        ),
+        (
+            "ipf-large",
+            "chip8-movie 2\nipf 1001\nend 1\n",
+            "line 2: ipf must be between 1 and 1000",
+        ),
+        (
+            "key",
+            "chip8-movie 2\n5 down 10\nend 1\n",
+            "line 2: invalid entry",
+        ),
+        (
+            "cut-short",
+            "chip8-movie 2\n5 down 1\n",
+            "the recording was cut short",
+        ),
+    ];
+
+    for (name, text, message) in table {
+        let path = path(name);
+        fs::write(&path, text).unwrap();
+
+        let error = Movie::load(&path).unwrap_err();
+
+        assert!(error.contains(message), "{}: {}", name, error);
+    }
+}
@@ -0,0 +1,692 @@
+use chip_8_emulator::{
+    cpu::{CPU, SCREEN_HEIGHT, SCREEN_WIDTH},
+    quirks::{Quirks, PROFILES},
+};
+
+// Every instruction run through CPU::update on a fresh machine, once for each
+// quirks profile. Each test is a table of inputs and what should come out,
+// worked out from the quirks where they matter.
+
+const START: u16 = 0x200;
+
+fn profiles() -> impl Iterator<Item = (&'static str, Quirks)> {
+    PROFILES
+        .iter()
+        .map(|&name| (name, Quirks::profile(name).unwrap()))
+}
+
+fn machine(quirks: Quirks) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.initialize();
+    cpu.reseed(0);
+    cpu.quirks = quirks;
+    cpu
+}
+
+// Runs a single instruction from where the program counter is
+fn step(cpu: &mut CPU, opcode: u16) {
+    let at = cpu.program_counter as usize;
+    cpu.game_memory[at..at + 2].copy_from_slice(&opcode.to_be_bytes());
+    cpu.update().unwrap();
+}
+
+fn lit_pixels(cpu: &CPU) -> Vec<(usize, usize)> {
+    (0..SCREEN_HEIGHT)
+        .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+        .filter(|&(x, y)| cpu.framebuffer[x + y * SCREEN_WIDTH])
+        .collect()
+}
+
+#[test]
+fn clear_screen_00e0() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+        cpu.framebuffer = [true; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+        step(&mut cpu, 0x00E0);
+
+        assert!(lit_pixels(&cpu).is_empty(), "{}", profile);
+        assert_eq!(cpu.program_counter, START + 2, "{}", profile);
+    }
+}
+
+#[test]
+fn machine_code_routine_0nnn_is_skipped() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+
+        step(&mut cpu, 0x0123);
+
+        assert_eq!(cpu.program_counter, START + 2, "{}", profile);
+    }
+}
+
+#[test]
+fn jump_1nnn() {
+    for (profile, quirks) in profiles() {
+        for target in [0x200, 0x2AE, 0xFFE] {
+            let mut cpu = machine(quirks);
+
+            step(&mut cpu, 0x1000 | target);
+
+            assert_eq!(cpu.program_counter, target, "{}: 1{:03X}", profile, target);
+        }
+    }
+}
+
+#[test]
+fn call_2nnn_and_return_00ee() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+
+        step(&mut cpu, 0x2400);
+        assert_eq!(cpu.program_counter, 0x400, "{}: call", profile);
+        assert_eq!(cpu.stack_pointer, 1, "{}: call", profile);
+
+        step(&mut cpu, 0x2600);
+        assert_eq!(cpu.program_counter, 0x600, "{}: nested call", profile);
+        assert_eq!(cpu.stack_pointer, 2, "{}: nested call", profile);
+
+        step(&mut cpu, 0x00EE);
+        assert_eq!(cpu.program_counter, 0x402, "{}: inner return", profile);
+        assert_eq!(cpu.stack_pointer, 1, "{}: inner return", profile);
+
+        step(&mut cpu, 0x00EE);
+        assert_eq!(cpu.program_counter, START + 2, "{}: outer return", profile);
+        assert_eq!(cpu.stack_pointer, 0, "{}: outer return", profile);
+    }
+}
+
+#[test]
+fn skips_3xnn_4xnn_5xy0_9xy0() {
+    // Opcode, V1, V2, whether the next instruction is skipped
+    let table = [
+        (0x3142, 0x42, 0x00, true),
+        (0x3142, 0x41, 0x00, false),
+        (0x3100, 0x00, 0x00, true),
+        (0x4142, 0x42, 0x00, false),
+        (0x4142, 0x41, 0x00, true),
+        (0x5120, 0x07, 0x07, true),
+        (0x5120, 0x07, 0x08, false),
+        (0x5110, 0x07, 0x08, true),
+        (0x9120, 0x07, 0x07, false),
+        (0x9120, 0x07, 0x08, true),
+        (0x9110, 0x07, 0x08, false),
+    ];
+
+    for (profile, quirks) in profiles() {
+        for (opcode, v1, v2, skip) in table {
+            let mut cpu = machine(quirks);
+            cpu.registers[1] = v1;
+            cpu.registers[2] = v2;
+
+            step(&mut cpu, opcode);
+
+            let expected = if skip { START + 4 } else { START + 2 };
+            assert_eq!(
+                cpu.program_counter, expected,
+                "{}: {:04X} with V1={:02X} V2={:02X}",
+                profile, opcode, v1, v2
+            );
+        }
+    }
+}
+
+#[test]
+fn load_6xnn_and_add_7xnn() {
+    // Opcode, V3 before, V3 after
+    let table = [
+        (0x6342, 0x00, 0x42),
+        (0x63FF, 0x12, 0xFF),
+        (0x7301, 0x41, 0x42),
+        (0x7301, 0xFF, 0x00),
+        (0x73FF, 0x02, 0x01),
+    ];
+
+    for (profile, quirks) in profiles() {
+        for (opcode, before, after) in table {
+            let mut cpu = machine(quirks);
+            cpu.registers[3] = before;
+            cpu.registers[0xF] = 0x55;
+
+            step(&mut cpu, opcode);
+
+            assert_eq!(cpu.registers[3], after, "{}: {:04X} on {:02X}", profile, opcode, before);
+            // 7XNN has no carry
+            assert_eq!(cpu.registers[0xF], 0x55, "{}: {:04X} touched VF", profile, opcode);
+            assert_eq!(cpu.program_counter, START + 2, "{}: {:04X}", profile, opcode);
+        }
+    }
+}
+
+#[test]
+fn logic_8xy0_to_8xy3() {
+    // Opcode, result for V1=0b1100 and V2=0b1010
+    let table = [
+        (0x8120, 0b1010, false),
+        (0x8121, 0b1110, true),
+        (0x8122, 0b1000, true),
+        (0x8123, 0b0110, true),
+    ];
+
+    for (profile, quirks) in profiles() {
+        for (opcode, result, resets_flag) in table {
+            let mut cpu = machine(quirks);
+            cpu.registers[1] = 0b1100;
+            cpu.registers[2] = 0b1010;
+            cpu.registers[0xF] = 0x55;
+
+            step(&mut cpu, opcode);
+
+            let flag = if resets_flag && quirks.logic { 0 } else { 0x55 };
+            assert_eq!(cpu.registers[1], result, "{}: {:04X}", profile, opcode);
+            assert_eq!(cpu.registers[0xF], flag, "{}: {:04X} VF", profile, opcode);
+            assert_eq!(cpu.program_counter, START + 2, "{}: {:04X}", profile, opcode);
+        }
+    }
+}
+
+#[test]
+fn arithmetic_8xy4_8xy5_8xy7() {
+    // Opcode, V1, V2, V1 after, VF after
+    let table = [
+        (0x8124, 0x01, 0x02, 0x03, 0),
+        (0x8124, 0x7F, 0x80, 0xFF, 0),
+        (0x8124, 0xFF, 0x01, 0x00, 1),
+        (0x8124, 0x80, 0x80, 0x00, 1),
+        (0x8124, 0xFF, 0xFF, 0xFE, 1),
+        (0x8124, 0x02, 0x01, 0x03, 0),
+        (0x8125, 0x05, 0x03, 0x02, 1),
+        (0x8125, 0x03, 0x03, 0x00, 1),
+        (0x8125, 0x03, 0x05, 0xFE, 0),
+        (0x8125, 0x00, 0xFF, 0x01, 0),
+        (0x8127, 0x03, 0x05, 0x02, 1),
+        (0x8127, 0x03, 0x03, 0x00, 1),
+        (0x8127, 0x05, 0x03, 0xFE, 0),
+        (0x8127, 0xFF, 0x00, 0x01, 0),
+    ];
+
+    for (profile, quirks) in profiles() {
+        for (opcode, v1, v2, result, flag) in table {
+            let mut cpu = machine(quirks);
+            cpu.registers[1] = v1;
+            cpu.registers[2] = v2;
+
+            step(&mut cpu, opcode);
+
+            let case = format!("{}: {:04X} with V1={:02X} V2={:02X}", profile, opcode, v1, v2);
+            assert_eq!(cpu.registers[1], result, "{}", case);
+            assert_eq!(cpu.registers[0xF], flag, "{} VF", case);
+            assert_eq!(cpu.program_counter, START + 2, "{}", case);
+        }
+    }
+}
+
+#[test]
+fn flag_wins_over_the_result_in_vf() {
+    // Opcode, VF, V1, VF after. None of the results equal the flag.
+    let table = [
+        (0x8F14, 0xFF, 0x01, 1),
+        (0x8F14, 0x01, 0x01, 0),
+        (0x8F15, 0x01, 0x02, 0),
+        (0x8F15, 0x05, 0x01, 1),
+        (0x8F17, 0x02, 0x01, 0),
+        (0x8F17, 0x01, 0x05, 1),
+        // VX and VY hold the same value, so the shift quirk makes no difference
+        (0x8F16, 0x05, 0x05, 1),
+        (0x8F1E, 0x81, 0x81, 1),
+    ];
+
+    for (profile, quirks) in profiles() {
+        for (opcode, vf, v1, flag) in table {
+            let mut cpu = machine(quirks);
+            cpu.registers[0xF] = vf;
+            cpu.registers[1] = v1;
+
+            step(&mut cpu, opcode);
+
+            assert_eq!(
+                cpu.registers[0xF], flag,
+                "{}: {:04X} with VF={:02X} V1={:02X}",
+                profile, opcode, vf, v1
+            );
+        }
+    }
+}
+
+#[test]
+fn carry_reads_vf_as_an_operand_before_writing_it() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+        cpu.registers[1] = 0x10;
+        cpu.registers[0xF] = 0x05;
+
+        step(&mut cpu, 0x81F5);
+
+        assert_eq!(cpu.registers[1], 0x0B, "{}", profile);
+        assert_eq!(cpu.registers[0xF], 1, "{}", profile);
+    }
+}
+
+#[test]
+fn shifts_8xy6_8xye() {
+    // Opcode, V1, V2, V1 after with VY shifted, VF, V1 after with VX shifted, VF
+    let table = [
+        (0x8126, 0x00, 0x05, 0x02, 1, 0x00, 0),
+        (0x8126, 0x05, 0x04, 0x02, 0, 0x02, 1),
+        (0x8126, 0xFF, 0x80, 0x40, 0, 0x7F, 1),
+        (0x812E, 0x00, 0x81, 0x02, 1, 0x00, 0),
+        (0x812E, 0x81, 0x01, 0x02, 0, 0x02, 1),
+        (0x812E, 0x40, 0xC0, 0x80, 1, 0x80, 0),
+    ];
+
+    for (profile, quirks) in profiles() {
+        for (opcode, v1, v2, from_y, flag_y, from_x, flag_x) in table {
+            let mut cpu = machine(quirks);
+            cpu.registers[1] = v1;
+            cpu.registers[2] = v2;
+
+            step(&mut cpu, opcode);
+
+            let (result, flag) = if quirks.shift { (from_x, flag_x) } else { (from_y, flag_y) };
+            let case = format!("{}: {:04X} with V1={:02X} V2={:02X}", profile, opcode, v1, v2);
+            assert_eq!(cpu.registers[1], result, "{}", case);
+            assert_eq!(cpu.registers[0xF], flag, "{} VF", case);
+            assert_eq!(cpu.registers[2], v2, "{} changed VY", case);
+            assert_eq!(cpu.program_counter, START + 2, "{}", case);
+        }
+    }
+}
+
+#[test]
+fn load_index_annn() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+
+        step(&mut cpu, 0xA123);
+
+        assert_eq!(cpu.index_register, 0x123, "{}", profile);
+        assert_eq!(cpu.program_counter, START + 2, "{}", profile);
+    }
+}
+
+#[test]
+fn jump_with_offset_bnnn() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+        cpu.registers[0] = 0x10;
+        cpu.registers[3] = 0x20;
+
+        step(&mut cpu, 0xB300);
+
+        let expected = if quirks.jump { 0x320 } else { 0x310 };
+        assert_eq!(cpu.program_counter, expected, "{}", profile);
+    }
+}
+
+#[test]
+fn random_cxnn_is_masked_and_seeded() {
+    for (profile, quirks) in profiles() {
+        for mask in [0x00, 0x0F, 0xF0, 0xFF] {
+            let mut first = machine(quirks);
+            let mut second = machine(quirks);
+
+            for _ in 0..32 {
+                step(&mut first, 0xC500 | mask);
+                step(&mut second, 0xC500 | mask);
+
+                assert_eq!(first.registers[5] & !mask as u8, 0, "{}: mask {:02X}", profile, mask);
+                assert_eq!(first.registers[5], second.registers[5], "{}: same seed", profile);
+            }
+        }
+    }
+}
+
+#[test]
+fn draw_dxyn_flips_pixels_and_reports_collisions() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+        cpu.index_register = 0x300;
+        cpu.game_memory[0x300] = 0b1000_0001;
+        cpu.game_memory[0x301] = 0b0100_0000;
+        cpu.registers[1] = 10;
+        cpu.registers[2] = 5;
+
+        step(&mut cpu, 0xD122);
+        assert_eq!(lit_pixels(&cpu), [(10, 5), (17, 5), (11, 6)], "{}: draw", profile);
+        assert_eq!(cpu.registers[0xF], 0, "{}: no collision", profile);
+        assert_eq!(cpu.program_counter, START + 2, "{}", profile);
+
+        step(&mut cpu, 0xD121);
+        assert_eq!(lit_pixels(&cpu), [(11, 6)], "{}: erase", profile);
+        assert_eq!(cpu.registers[0xF], 1, "{}: collision", profile);
+    }
+}
+
+#[test]
+fn draw_dxyn_at_the_edges() {
+    for (profile, quirks) in profiles() {
+        // A 2x2 block at the bottom right corner
+        let mut cpu = machine(quirks);
+        cpu.index_register = 0x300;
+        cpu.game_memory[0x300] = 0b1100_0000;
+        cpu.game_memory[0x301] = 0b1100_0000;
+        cpu.registers[1] = 63;
+        cpu.registers[2] = 31;
+
+        step(&mut cpu, 0xD122);
+
+        let expected: &[(usize, usize)] = if quirks.wrap {
+            &[(0, 0), (63, 0), (0, 31), (63, 31)]
+        } else {
+            &[(63, 31)]
+        };
+        assert_eq!(lit_pixels(&cpu), expected, "{}: corner", profile);
+
+        // The starting position wraps whatever the quirks
+        let mut cpu = machine(quirks);
+        cpu.index_register = 0x300;
+        cpu.game_memory[0x300] = 0b1000_0000;
+        cpu.registers[1] = 64 + 3;
+        cpu.registers[2] = 32 + 4;
+
+        step(&mut cpu, 0xD121);
+
+        assert_eq!(lit_pixels(&cpu), [(3, 4)], "{}: start position", profile);
+    }
+}
+
+#[test]
+fn draw_dxyn_waits_for_vblank() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+        cpu.instructions_per_frame = 10;
+        // Draws the same pixel over and over
+        cpu.index_register = 0x0;
+        for at in (START as usize..START as usize + 20).step_by(2) {
+            cpu.game_memory[at..at + 2].copy_from_slice(&0xD001u16.to_be_bytes());
+        }
+
+        cpu.run_frame().unwrap();
+
+        let expected = if quirks.vblank { START + 2 } else { START + 20 };
+        assert_eq!(cpu.program_counter, expected, "{}", profile);
+    }
+}
+
+#[test]
+fn key_skips_ex9e_exa1() {
+    // Opcode, whether key 7 is held, whether the next instruction is skipped
+    let table = [
+        (0xE19E, true, true),
+        (0xE19E, false, false),
+        (0xE1A1, true, false),
+        (0xE1A1, false, true),
+    ];
+
+    for (profile, quirks) in profiles() {
+        for (opcode, held, skip) in table {
+            let mut cpu = machine(quirks);
+            cpu.registers[1] = 0x7;
+            if held {
+                cpu.key_down(0x7);
+            }
+            // Other keys make no difference
+            cpu.key_down(0x3);
+
+            step(&mut cpu, opcode);
+
+            let expected = if skip { START + 4 } else { START + 2 };
+            assert_eq!(cpu.program_counter, expected, "{}: {:04X} held={}", profile, opcode, held);
+        }
+    }
+}
+
+#[test]
+fn timers_fx07_fx15_fx18() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+        cpu.registers[1] = 3;
+        cpu.registers[2] = 2;
+
+        step(&mut cpu, 0xF115);
+        step(&mut cpu, 0xF218);
+        assert_eq!((cpu.delay_timer, cpu.sound_timer), (3, 2), "{}: set", profile);
+        assert!(cpu.sound_active(), "{}", profile);
+
+        cpu.tick_timers();
+        cpu.tick_timers();
+        step(&mut cpu, 0xF307);
+        assert_eq!(cpu.registers[3], 1, "{}: read delay", profile);
+        assert!(!cpu.sound_active(), "{}", profile);
+
+        cpu.tick_timers();
+        cpu.tick_timers();
+        assert_eq!((cpu.delay_timer, cpu.sound_timer), (0, 0), "{}: stop at 0", profile);
+        assert_eq!(cpu.program_counter, START + 6, "{}", profile);
+    }
+}
+
+#[test]
+fn wait_for_key_fx0a() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+        // A key held from before the wait does not count
+        cpu.key_down(0x1);
+
+        step(&mut cpu, 0xF50A);
+        step(&mut cpu, 0xF50A);
+        assert_eq!(cpu.program_counter, START, "{}: waits", profile);
+
+        cpu.key_down(0xA);
+        cpu.update().unwrap();
+
+        if quirks.key_wait_release {
+            assert_eq!(cpu.program_counter, START, "{}: waits for the release", profile);
+
+            cpu.key_up(0xA);
+            cpu.update().unwrap();
+        }
+
+        assert_eq!(cpu.program_counter, START + 2, "{}: done", profile);
+        assert_eq!(cpu.registers[5], 0xA, "{}: key", profile);
+    }
+}
+
+#[test]
+fn add_to_index_fx1e() {
+    // I, V1, I after
+    let table = [(0x100, 0x01, 0x101), (0x2F0, 0xFF, 0x3EF), (0xFFF, 0x01, 0x000)];
+
+    for (profile, quirks) in profiles() {
+        for (index, v1, result) in table {
+            let mut cpu = machine(quirks);
+            cpu.index_register = index;
+            cpu.registers[1] = v1;
+            cpu.registers[0xF] = 0x55;
+
+            step(&mut cpu, 0xF11E);
+
+            assert_eq!(cpu.index_register, result, "{}: {:03X} + {:02X}", profile, index, v1);
+            assert_eq!(cpu.registers[0xF], 0x55, "{}: VF", profile);
+        }
+    }
+}
+
+#[test]
+fn font_character_fx29() {
+    for (profile, quirks) in profiles() {
+        for digit in 0..=0xF {
+            let mut cpu = machine(quirks);
+            cpu.registers[2] = digit;
+
+            step(&mut cpu, 0xF229);
+
+            let index = cpu.index_register as usize;
+            assert_eq!(index, digit as usize * 5, "{}: digit {:X}", profile, digit);
+            assert!(cpu.game_memory[index..index + 5].iter().any(|&row| row != 0));
+        }
+
+        // Only the low nibble picks the character
+        let mut cpu = machine(quirks);
+        cpu.registers[2] = 0x1B;
+        step(&mut cpu, 0xF229);
+        assert_eq!(cpu.index_register, 0xB * 5, "{}: high nibble", profile);
+    }
+}
+
+#[test]
+fn binary_coded_decimal_fx33() {
+    let table = [(0, [0, 0, 0]), (9, [0, 0, 9]), (10, [0, 1, 0]), (123, [1, 2, 3]), (255, [2, 5, 5])];
+
+    for (profile, quirks) in profiles() {
+        for (value, digits) in table {
+            let mut cpu = machine(quirks);
+            cpu.index_register = 0x300;
+            cpu.registers[4] = value;
+
+            step(&mut cpu, 0xF433);
+
+            assert_eq!(cpu.game_memory[0x300..0x303], digits, "{}: {}", profile, value);
+            assert_eq!(cpu.index_register, 0x300, "{}: I", profile);
+        }
+    }
+}
+
+#[test]
+fn store_fx55_and_load_fx65() {
+    for (profile, quirks) in profiles() {
+        for x in [0x0, 0x3, 0xF] {
+            let index_after = if quirks.memory_leave_i_unchanged {
+                0x300
+            } else if quirks.memory_increment_by_x {
+                0x300 + x
+            } else {
+                0x300 + x + 1
+            };
+
+            let mut cpu = machine(quirks);
+            cpu.index_register = 0x300;
+            for (register, value) in cpu.registers.iter_mut().enumerate() {
+                *value = 0x10 + register as u8;
+            }
+
+            step(&mut cpu, 0xF055 | x << 8);
+
+            let stored: Vec<u8> = (0..=x as u8).map(|register| 0x10 + register).collect();
+            assert_eq!(cpu.game_memory[0x300..=0x300 + x as usize], stored, "{}: F{:X}55", profile, x);
+            assert_eq!(cpu.game_memory[0x301 + x as usize], 0, "{}: F{:X}55 past VX", profile, x);
+            assert_eq!(cpu.index_register, index_after, "{}: F{:X}55 I", profile, x);
+
+            let mut cpu = machine(quirks);
+            cpu.index_register = 0x300;
+            for (offset, value) in cpu.game_memory[0x300..0x310].iter_mut().enumerate() {
+                *value = 0x80 + offset as u8;
+            }
+
+            step(&mut cpu, 0xF065 | x << 8);
+
+            let loaded: Vec<u8> = (0..=x as u8).map(|register| 0x80 + register).collect();
+            assert_eq!(cpu.registers[..=x as usize], loaded, "{}: F{:X}65", profile, x);
+            if x < 0xF {
+                assert_eq!(cpu.registers[x as usize + 1], 0, "{}: F{:X}65 past VX", profile, x);
+            }
+            assert_eq!(cpu.index_register, index_after, "{}: F{:X}65 I", profile, x);
+            assert_eq!(cpu.program_counter, START + 2, "{}", profile);
+        }
+    }
+}
+
+#[test]
+fn audio_pattern_f002_and_pitch_fx3a() {
+    for (profile, quirks) in profiles() {
+        let mut cpu = machine(quirks);
+        assert_eq!(cpu.sound_pattern(), None, "{}: buzzer until F002", profile);
+
+        let pattern: [u8; 16] = std::array::from_fn(|i| i as u8 * 17);
+        cpu.game_memory[0x300..0x310].copy_from_slice(&pattern);
+        cpu.index_register = 0x300;
+        cpu.registers[4] = 112;
+
+        step(&mut cpu, 0xF002);
+        step(&mut cpu, 0xF43A);
+
+        let sound = cpu.sound_pattern().unwrap();
+        assert_eq!(sound.bits, pattern, "{}", profile);
+        assert_eq!(sound.pitch, 112, "{}", profile);
+        // 48 steps over the default pitch is an octave up
+        assert_eq!(sound.rate(), 8000.0, "{}", profile);
+        assert_eq!(cpu.index_register, 0x300, "{}: I stays", profile);
+        assert_eq!(cpu.program_counter, START + 4, "{}", profile);
+    }
+}
+
+#[test]
+fn unknown_opcodes_are_errors() {
+    for (profile, quirks) in profiles() {
+        for opcode in [0x5121, 0x812F, 0xE1FF, 0xF1FF] {
+            let mut cpu = machine(quirks);
+            let at = START as usize;
+            cpu.game_memory[at..at + 2].copy_from_slice(&u16::to_be_bytes(opcode));
+
+            let error = cpu.update().unwrap_err();
+
+            assert_eq!(
+                error,
+                format!("200: {:04X} DW 0x{:04X}: unknown opcode", opcode, opcode),
+                "{}",
+                profile
+            );
+            assert_eq!(cpu.program_counter, START, "{}: {:04X}", profile, opcode);
+        }
+    }
+}
+
+#[test]
+fn frame_count_wraps_after_a_loaded_state() {
+    let mut cpu = machine(Quirks::default());
+    cpu.instructions_per_frame = 0;
+    let mut state = cpu.save_state();
+
+    // The frame count sits just before the 8 bytes of the random generator
+    let at = state.len() - 16;
+    state[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    cpu.load_state(&state).unwrap();
+    cpu.run_frame().unwrap();
+
+    assert_eq!(cpu.frame_count, 0);
+}
+
+#[test]
+fn errors_instead_of_panics() {
+    // PC, stack depth, I, opcode and what the error is about
+    let table = [
+        (START, 16, 0x000, 0x2300, "stack"),
+        (START, 0, 0x000, 0x00EE, "empty stack"),
+        (START, 0, 0xFFE, 0xF033, "past the end of memory"),
+        (START, 0, 0xFFF, 0xF155, "past the end of memory"),
+        (START, 0, 0xFF8, 0xFF65, "past the end of memory"),
+        (START, 0, 0xFFC, 0xD005, "past the end of memory"),
+        (START, 0, 0xFF8, 0xF002, "past the end of memory"),
+        (0xFFF, 0, 0x000, 0x0000, "off the end of memory"),
+    ];
+
+    for (profile, quirks) in profiles() {
+        for (pc, depth, index, opcode, message) in table {
+            let mut cpu = machine(quirks);
+            cpu.program_counter = pc;
+            cpu.stack_pointer = depth;
+            cpu.index_register = index;
+            let at = (pc as usize).min(0xFFE);
+            cpu.game_memory[at..at + 2].copy_from_slice(&u16::to_be_bytes(opcode));
+            let registers = cpu.registers;
+
+            let error = cpu.update().unwrap_err();
+
+            // The machine stays on the instruction that failed
+            assert!(error.contains(message), "{}: {:04X}: {}", profile, opcode, error);
+            assert_eq!(cpu.program_counter, pc, "{}: {:04X}", profile, opcode);
+            assert_eq!(cpu.registers, registers, "{}: {:04X}", profile, opcode);
+        }
+    }
+}
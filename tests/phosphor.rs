@@ -0,0 +1,47 @@
+use chip_8_emulator::{
+    cpu::{Framebuffer, SCREEN_HEIGHT, SCREEN_WIDTH},
+    dirty::DirtyRegion,
+    phosphor::{Phosphor, FULL_BRIGHTNESS},
+};
+
+// Brightness of the first pixel over the frames after it is erased
+fn fade_out(fade_frames: u8) -> Vec<u8> {
+    let mut phosphor = Phosphor::new(fade_frames);
+    let mut dirty = DirtyRegion::default();
+    let mut framebuffer: Framebuffer = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+    framebuffer[0] = true;
+    assert_eq!(
+        phosphor.update(&framebuffer, SCREEN_WIDTH, &mut dirty)[0],
+        FULL_BRIGHTNESS
+    );
+
+    framebuffer[0] = false;
+    (0..=fade_frames as usize)
+        .map(|_| phosphor.update(&framebuffer, SCREEN_WIDTH, &mut dirty)[0])
+        .collect()
+}
+
+#[test]
+fn erased_pixels_glow_for_exactly_the_fade_frames() {
+    assert_eq!(fade_out(0), [0]);
+    assert_eq!(fade_out(3), [192, 128, 64, 0]);
+
+    for fade_frames in [1, 2, 10, 100, 254, 255] {
+        let levels = fade_out(fade_frames);
+        let (last, glowing) = levels.split_last().unwrap();
+
+        assert_eq!(*last, 0, "{}", fade_frames);
+        assert!(
+            glowing.iter().all(|&level| level > 0),
+            "{}: {:?}",
+            fade_frames,
+            levels
+        );
+        assert!(
+            levels.windows(2).all(|pair| pair[0] > pair[1]),
+            "{}",
+            fade_frames
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use chip_8_emulator::{cpu::Random, quirks::Quirks};
+
+// A second CHIP-8 written straight from the instruction set tables, for the
+// differential tests only. It shares nothing with CPU except the random
+// number generator, and favours being easy to check over being fast.
+//
+// Anything the instruction set leaves undefined (a full or empty stack,
+// memory past 4 KiB) stops the machine instead of picking a behaviour.
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+pub const MEMORY: usize = 4096;
+pub const STACK: usize = 16;
+
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// Why the machine stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Undefined {
+    StackOverflow,
+    StackUnderflow,
+    MemoryOutOfRange,
+    UnknownOpcode,
+}
+
+pub struct Machine {
+    pub memory: Vec<u8>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    // Return addresses, innermost last
+    pub stack: Vec<u16>,
+    pub delay: u8,
+    pub sound: u8,
+    // Rows of pixels
+    pub screen: [[bool; WIDTH]; HEIGHT],
+    pub keys: [bool; 16],
+    pub quirks: Quirks,
+    pub random: Random,
+}
+
+impl Machine {
+    pub fn new(program: &[u8], quirks: Quirks, seed: u64) -> Self {
+        let mut memory = vec![0; MEMORY];
+        memory[..FONT.len()].copy_from_slice(&FONT);
+        memory[0x200..0x200 + program.len()].copy_from_slice(program);
+
+        Machine {
+            memory,
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: Vec::new(),
+            delay: 0,
+            sound: 0,
+            screen: [[false; WIDTH]; HEIGHT],
+            keys: [false; 16],
+            quirks,
+            random: Random::new(seed),
+        }
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    // Reads `length` bytes of memory from I on
+    fn read(&self, length: usize) -> Result<Vec<u8>, Undefined> {
+        let start = self.i as usize;
+        match self.memory.get(start..start + length) {
+            Some(bytes) => Ok(bytes.to_vec()),
+            None => Err(Undefined::MemoryOutOfRange),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Undefined> {
+        let start = self.i as usize;
+        match self.memory.get_mut(start..start + bytes.len()) {
+            Some(memory) => {
+                memory.copy_from_slice(bytes);
+                Ok(())
+            }
+            None => Err(Undefined::MemoryOutOfRange),
+        }
+    }
+
+    // Runs the instruction at PC
+    pub fn step(&mut self) -> Result<(), Undefined> {
+        let pc = self.pc as usize;
+        if pc + 1 >= MEMORY {
+            return Err(Undefined::MemoryOutOfRange);
+        }
+
+        let opcode = u16::from_be_bytes([self.memory[pc], self.memory[pc + 1]]);
+        let kind = opcode >> 12;
+        let x = ((opcode >> 8) & 0xF) as usize;
+        let y = ((opcode >> 4) & 0xF) as usize;
+        let n = (opcode & 0xF) as usize;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+
+        let next = self.pc + 2;
+        let skip = self.pc + 4;
+
+        match (kind, x, y, n) {
+            // 00E0: clear the screen
+            (0x0, 0x0, 0xE, 0x0) => {
+                self.screen = [[false; WIDTH]; HEIGHT];
+                self.pc = next;
+            }
+            // 00EE: return
+            (0x0, 0x0, 0xE, 0xE) => {
+                self.pc = self.stack.pop().ok_or(Undefined::StackUnderflow)?;
+            }
+            // 0NNN: machine code, ignored
+            (0x0, ..) => self.pc = next,
+            // 1NNN: jump
+            (0x1, ..) => self.pc = nnn,
+            // 2NNN: call
+            (0x2, ..) => {
+                if self.stack.len() == STACK {
+                    return Err(Undefined::StackOverflow);
+                }
+                self.stack.push(next);
+                self.pc = nnn;
+            }
+            // 3XNN, 4XNN, 5XY0, 9XY0: skips
+            (0x3, ..) => self.pc = if self.v[x] == nn { skip } else { next },
+            (0x4, ..) => self.pc = if self.v[x] != nn { skip } else { next },
+            (0x5, _, _, 0x0) => self.pc = if self.v[x] == self.v[y] { skip } else { next },
+            (0x9, _, _, 0x0) => self.pc = if self.v[x] != self.v[y] { skip } else { next },
+            // 6XNN, 7XNN
+            (0x6, ..) => {
+                self.v[x] = nn;
+                self.pc = next;
+            }
+            (0x7, ..) => {
+                self.v[x] = ((self.v[x] as u16 + nn as u16) % 256) as u8;
+                self.pc = next;
+            }
+            // 8XYN: arithmetic, the flag is written last
+            (0x8, _, _, 0x0..=0x7 | 0xE) => {
+                let vx = self.v[x] as u16;
+                let vy = self.v[y] as u16;
+
+                let (result, flag) = match n {
+                    0x0 => (vy, None),
+                    0x1 => (vx | vy, self.quirks.logic.then_some(0)),
+                    0x2 => (vx & vy, self.quirks.logic.then_some(0)),
+                    0x3 => (vx ^ vy, self.quirks.logic.then_some(0)),
+                    0x4 => ((vx + vy) % 256, Some((vx + vy > 255) as u8)),
+                    0x5 => ((vx + 256 - vy) % 256, Some((vx >= vy) as u8)),
+                    0x7 => ((vy + 256 - vx) % 256, Some((vy >= vx) as u8)),
+                    0x6 => {
+                        let source = if self.quirks.shift { vx } else { vy };
+                        (source / 2, Some((source % 2) as u8))
+                    }
+                    _ => {
+                        let source = if self.quirks.shift { vx } else { vy };
+                        ((source * 2) % 256, Some((source / 128) as u8))
+                    }
+                };
+
+                self.v[x] = result as u8;
+                if let Some(flag) = flag {
+                    self.v[0xF] = flag;
+                }
+                self.pc = next;
+            }
+            // ANNN
+            (0xA, ..) => {
+                self.i = nnn;
+                self.pc = next;
+            }
+            // BNNN, or BXNN with the jump quirk
+            (0xB, ..) => {
+                let offset = if self.quirks.jump { self.v[x] } else { self.v[0] };
+                self.pc = nnn + offset as u16;
+            }
+            // CXNN
+            (0xC, ..) => {
+                self.v[x] = self.random.next_u8() & nn;
+                self.pc = next;
+            }
+            // DXYN
+            (0xD, ..) => {
+                let sprite = self.read(n)?;
+                let left = self.v[x] as usize % WIDTH;
+                let top = self.v[y] as usize % HEIGHT;
+                let mut collision = false;
+
+                for (row, bits) in sprite.iter().enumerate() {
+                    for column in 0..8 {
+                        if bits & (0x80 >> column) == 0 {
+                            continue;
+                        }
+
+                        let (mut px, mut py) = (left + column, top + row);
+                        if px >= WIDTH || py >= HEIGHT {
+                            if !self.quirks.wrap {
+                                continue;
+                            }
+                            px %= WIDTH;
+                            py %= HEIGHT;
+                        }
+
+                        collision |= self.screen[py][px];
+                        self.screen[py][px] = !self.screen[py][px];
+                    }
+                }
+
+                self.v[0xF] = collision as u8;
+                self.pc = next;
+            }
+            // EX9E, EXA1
+            (0xE, _, 0x9, 0xE) => {
+                self.pc = if self.keys[self.v[x] as usize % 16] { skip } else { next }
+            }
+            (0xE, _, 0xA, 0x1) => {
+                self.pc = if !self.keys[self.v[x] as usize % 16] { skip } else { next }
+            }
+            // FXNN
+            (0xF, _, 0x0, 0x7) => {
+                self.v[x] = self.delay;
+                self.pc = next;
+            }
+            // FX0A waits while no key goes down, these tests press none
+            (0xF, _, 0x0, 0xA) => {}
+            (0xF, _, 0x1, 0x5) => {
+                self.delay = self.v[x];
+                self.pc = next;
+            }
+            (0xF, _, 0x1, 0x8) => {
+                self.sound = self.v[x];
+                self.pc = next;
+            }
+            (0xF, _, 0x1, 0xE) => {
+                self.i = (self.i + self.v[x] as u16) % 4096;
+                self.pc = next;
+            }
+            (0xF, _, 0x2, 0x9) => {
+                self.i = (self.v[x] % 16) as u16 * 5;
+                self.pc = next;
+            }
+            (0xF, _, 0x3, 0x3) => {
+                let value = self.v[x];
+                self.write(&[value / 100, value / 10 % 10, value % 10])?;
+                self.pc = next;
+            }
+            (0xF, _, 0x5, 0x5) => {
+                let registers = self.v[..=x].to_vec();
+                self.write(&registers)?;
+                self.i = self.index_after_memory(x);
+                self.pc = next;
+            }
+            (0xF, _, 0x6, 0x5) => {
+                let bytes = self.read(x + 1)?;
+                self.v[..=x].copy_from_slice(&bytes);
+                self.i = self.index_after_memory(x);
+                self.pc = next;
+            }
+            // Anything else is not an instruction
+            _ => return Err(Undefined::UnknownOpcode),
+        }
+
+        Ok(())
+    }
+
+    fn index_after_memory(&self, x: usize) -> u16 {
+        if self.quirks.memory_leave_i_unchanged {
+            self.i
+        } else if self.quirks.memory_increment_by_x {
+            self.i + x as u16
+        } else {
+            self.i + x as u16 + 1
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use chip_8_emulator::rewind::{Rewind, REWIND_INTERVAL};
+
+// States are a few bytes that count up with every push, so the state a step
+// back lands on tells how far back it is.
+
+fn state(count: usize) -> Vec<u8> {
+    vec![count as u8, (count >> 8) as u8, 0, 0xFF]
+}
+
+#[test]
+fn keeps_a_full_buffer_of_history() {
+    let seconds = 1;
+    let capacity = (seconds * 60 / REWIND_INTERVAL) as usize;
+    let pushed = capacity * 3;
+
+    let mut rewind = Rewind::new(seconds);
+    for count in 0..pushed {
+        rewind.push(state(count));
+    }
+
+    // Every step goes one snapshot further back, as far as the capacity
+    for depth in 1..=capacity {
+        let back = rewind.step_back().map(<[u8]>::to_vec);
+        assert_eq!(back, Some(state(pushed - 1 - depth)), "step {}", depth);
+    }
+
+    assert_eq!(rewind.step_back(), None);
+}
+
+#[test]
+fn a_short_history_rewinds_to_the_start() {
+    let mut rewind = Rewind::new(1);
+    for count in 0..5 {
+        rewind.push(state(count));
+    }
+
+    for count in (0..4).rev() {
+        assert_eq!(rewind.step_back().map(<[u8]>::to_vec), Some(state(count)));
+    }
+
+    assert_eq!(rewind.step_back(), None);
+}
+
+#[test]
+fn zero_seconds_keeps_nothing() {
+    let mut rewind = Rewind::new(0);
+    rewind.push(state(0));
+    rewind.push(state(1));
+
+    assert_eq!(rewind.step_back(), None);
+}
+
+#[test]
+fn any_number_of_seconds_is_accepted() {
+    let mut rewind = Rewind::new(u64::MAX);
+    rewind.push(state(0));
+    rewind.push(state(1));
+
+    assert_eq!(rewind.step_back().map(<[u8]>::to_vec), Some(state(0)));
+}